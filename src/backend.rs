@@ -0,0 +1,168 @@
+use crate::client::{Client, ClientOptions, Mode};
+use crate::db::DB;
+use crate::index::*;
+use crate::tantivy::{Tantivy, TantivyOptions};
+use quick_error::quick_error;
+use std::error::Error;
+use std::net::ToSocketAddrs;
+use std::str::FromStr;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum BackendError {
+        UnknownBackend(name: String) {
+            display("Error unknown backend \"{}\", expected tantivy or sonic", name)
+        }
+        BadAddress(addr: String) {
+            display("Error could not resolve sonic address \"{}\"", addr)
+        }
+    }
+}
+
+/// Which search index to use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// A local index directory, no services needed
+    Tantivy,
+    /// A sonic server, with documents looked up in MongoDB
+    Sonic,
+}
+
+/// Accepts `tantivy` and `sonic`.
+impl FromStr for Backend {
+    type Err = BackendError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tantivy" => Ok(Self::Tantivy),
+            "sonic" => Ok(Self::Sonic),
+            _ => Err(BackendError::UnknownBackend(String::from(s))),
+        }
+    }
+}
+
+/// Everything needed to open either backend.
+#[derive(Debug, Clone)]
+pub struct BackendOptions {
+    pub backend: Backend,
+    pub index_dir: String,
+    pub sonic_addr: String,
+    pub sonic_password: String,
+    /// Ingest connections opened for rebuilding
+    pub sonic_connections: usize,
+    pub mongo_addr: String,
+}
+
+/// An open index of whichever backend was chosen.
+#[derive(Clone)]
+pub enum Engine {
+    Tantivy(Tantivy),
+    Sonic(Client),
+}
+
+impl Engine {
+    /// Opens the index for searching.
+    pub fn open(options: &BackendOptions) -> Result<Self, Box<dyn Error>> {
+        match options.backend {
+            Backend::Tantivy => Ok(Self::Tantivy(Tantivy::new(TantivyOptions {
+                index_dir: options.index_dir.clone(),
+                ..TantivyOptions::default()
+            }))),
+            Backend::Sonic => {
+                let db = DB::connect(&options.mongo_addr)?;
+                Ok(Self::Sonic(connect_sonic(options, Mode::Search)?.with_store(db)))
+            }
+        }
+    }
+
+    /// Opens the index for rebuilding. Tantivy starts from an empty index directory.
+    pub fn rebuild(options: &BackendOptions) -> Result<Self, Box<dyn Error>> {
+        match options.backend {
+            Backend::Tantivy => Ok(Self::Tantivy(Tantivy::new(TantivyOptions {
+                rebuild: true,
+                index_dir: options.index_dir.clone(),
+            }))),
+            Backend::Sonic => Ok(Self::Sonic(connect_sonic(options, Mode::Ingest)?)),
+        }
+    }
+}
+
+/// Connects to the sonic server on one of its channels. Ingest clients get `sonic_connections`
+/// connections for bulk indexing.
+pub fn connect_sonic(options: &BackendOptions, mode: Mode) -> Result<Client, Box<dyn Error>> {
+    let addr = options
+        .sonic_addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| BackendError::BadAddress(options.sonic_addr.clone()))?;
+    let connections = match mode {
+        Mode::Ingest => options.sonic_connections,
+        _ => 1,
+    };
+    Client::connect_pool(
+        ClientOptions {
+            addr,
+            password: &options.sonic_password,
+            mode,
+            ..ClientOptions::default()
+        },
+        connections,
+    )
+}
+
+impl Indexer for Engine {
+    fn index<T: Index>(&self, idx: Box<T>) -> Result<(), IndexError> {
+        match self {
+            Self::Tantivy(t) => t.index(idx),
+            Self::Sonic(c) => c.index(idx),
+        }
+    }
+
+    fn index_bulk<T: Index>(&self, idx: Vec<Box<T>>) -> Result<(), IndexError> {
+        match self {
+            Self::Tantivy(t) => t.index_bulk(idx),
+            Self::Sonic(c) => c.index_bulk(idx),
+        }
+    }
+
+    fn query_ids(&self, col: &str, query: &str) -> Result<Vec<String>, IndexError> {
+        match self {
+            Self::Tantivy(t) => t.query_ids(col, query),
+            Self::Sonic(c) => c.query_ids(col, query),
+        }
+    }
+
+    fn query(&self, col: &str, query: &str) -> Result<Vec<(String, Vec<u8>)>, IndexError> {
+        match self {
+            Self::Tantivy(t) => t.query(col, query),
+            Self::Sonic(c) => c.query(col, query),
+        }
+    }
+
+    fn flush_all(&self, col: &str) -> Result<(), IndexError> {
+        match self {
+            Self::Tantivy(t) => t.flush_all(col),
+            Self::Sonic(c) => c.flush_all(col),
+        }
+    }
+
+    fn suggest(&self, col: &str, word: &str, limit: usize) -> Result<Vec<String>, IndexError> {
+        match self {
+            Self::Tantivy(t) => t.suggest(col, word, limit),
+            Self::Sonic(c) => c.suggest(col, word, limit),
+        }
+    }
+
+    fn query_page(
+        &self,
+        col: &str,
+        query: &str,
+        cursor: &mut Cursor,
+        limit: usize,
+    ) -> Result<Page, IndexError> {
+        match self {
+            Self::Tantivy(t) => t.query_page(col, query, cursor, limit),
+            Self::Sonic(c) => c.query_page(col, query, cursor, limit),
+        }
+    }
+}
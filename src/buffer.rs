@@ -0,0 +1,66 @@
+use tuikit::canvas;
+use tuikit::prelude::*;
+use unicode_width::UnicodeWidthChar;
+
+/// Rows past this are dropped; no entry comes close.
+const MAX_ROWS: usize = 10_000;
+
+/// A canvas that keeps what is drawn in memory rather than showing it. Drawing an entry into a
+/// buffer gives its text line by line, as laid out for a given width.
+pub struct Buffer {
+    width: usize,
+    rows: Vec<Vec<char>>,
+}
+
+impl Buffer {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            rows: Vec::new(),
+        }
+    }
+
+    /// The text drawn on each row, with trailing space removed. Wide characters are followed
+    /// by a space so that character and column indexes line up.
+    pub fn lines(&self) -> Vec<String> {
+        self.rows
+            .iter()
+            .map(|r| r.iter().collect::<String>().trim_end().to_string())
+            .collect()
+    }
+}
+
+impl Canvas for Buffer {
+    fn size(&self) -> canvas::Result<(usize, usize)> {
+        Ok((self.width, MAX_ROWS))
+    }
+
+    fn clear(&mut self) -> canvas::Result<()> {
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) -> canvas::Result<usize> {
+        let width = cell.ch.width().unwrap_or(0);
+        if row >= MAX_ROWS || col >= self.width {
+            return Ok(width);
+        }
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, Vec::new());
+        }
+        let r = &mut self.rows[row];
+        if r.len() < self.width {
+            r.resize(self.width, ' ');
+        }
+        r[col] = cell.ch;
+        Ok(width)
+    }
+
+    fn set_cursor(&mut self, _row: usize, _col: usize) -> canvas::Result<()> {
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, _show: bool) -> canvas::Result<()> {
+        Ok(())
+    }
+}
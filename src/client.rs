@@ -1,40 +1,95 @@
-use crate::worker::TcpStreamWorker;
+use crate::connection::Connection;
 use crate::index::*;
 use derivative::Derivative;
+use quick_error::quick_error;
 // use log::{debug, info, warn};
-// use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::net::SocketAddr;
 use std::result::Result;
 // use std::slice::Split;
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+use mio::{Events, Poll, Token, Waker};
+use std::io;
 use std::sync::{
-    mpsc::{channel, Receiver, RecvTimeoutError, Sender},
-    Arc, Condvar, Mutex,
+    mpsc::{channel, Receiver, Sender, TryRecvError},
+    Arc, Mutex,
 };
 use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const RESULTS_MIN: usize = 10;
 
+/// Pushes in flight at once on each connection during bulk indexing
+const PIPELINE_DEPTH: usize = 64;
+
+/// Line size sonic allows when it doesn't say, in bytes
+const DEFAULT_BUFFER: usize = 20_000;
+
+/// Longest wait between reconnect attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long to wait for the server to end the connection after QUIT
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+const SOCKET: Token = Token(0);
+const WAKER: Token = Token(1);
+
 #[derive(Debug)]
 pub enum ClientError {
     // ConnectionError,
     ProcessingError,
+    /// The server answered with something other than what the request calls for
+    UnexpectedResponse(String),
+    /// No reply within the request timeout
+    Timeout,
+    /// The connection dropped before the reply came
+    ConnectionLost,
 }
 
 impl Display for ClientError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)?;
-        Ok(())
+        match self {
+            Self::ProcessingError => write!(f, "Error processing sonic request"),
+            Self::UnexpectedResponse(res) => write!(f, "Error unexpected sonic response: {}", res),
+            Self::Timeout => write!(f, "Error timed out waiting for sonic"),
+            Self::ConnectionLost => write!(f, "Error lost the connection to sonic"),
+        }
     }
 }
 
 impl Error for ClientError {}
 
+quick_error! {
+    /// A line from sonic that couldn't be understood, or an error reported by the server.
+    #[derive(Debug)]
+    pub enum ResponseError {
+        Empty {
+            display("Error empty response from sonic")
+        }
+        UnknownResponse(line: String) {
+            display("Error unknown sonic response \"{}\"", line)
+        }
+        UnknownEvent(kind: String) {
+            display("Error unknown sonic event \"{}\"", kind)
+        }
+        Malformed(line: String) {
+            display("Error malformed sonic response \"{}\"", line)
+        }
+        Server(reason: String) {
+            display("Error from sonic: {}", reason)
+        }
+    }
+}
+
 pub trait Message: Display {}
 
+#[derive(Clone, Copy)]
 pub enum Mode {
     Search,
     Control,
@@ -80,19 +135,30 @@ impl Message for RequestMessage<'_> {}
 #[derive(Debug)]
 pub enum ResponseMessage {
     Connected,
-    Started,
+    Started(Started),
     Ended,
     Pending(Pending),
     Event(Event),
-    Result,
+    /// A count, e.g. the number of objects flushed or words popped
+    Result(usize),
+    /// Server statistics answering INFO, as (name, value) pairs
+    Info(Vec<(String, String)>),
     Pong,
     Ok,
-    Err,
+    /// The reason given, e.g. `invalid_format(QUERY <collection> <bucket> "<terms>")`
+    Err(String),
 }
 
 pub enum IngestRequestMessage<'a> {
+    /// Collection, bucket, object and text
     Push(&'a str, &'a str, &'a str, &'a str),
+    /// Removes the words of the text from an object
+    Pop(&'a str, &'a str, &'a str, &'a str),
+    /// Counts the buckets in a collection, the objects in a bucket or the words in an object
+    Count(&'a str, Option<&'a str>, Option<&'a str>),
     Flushc(&'a str),
+    Flushb(&'a str, &'a str),
+    Flusho(&'a str, &'a str, &'a str),
 }
 
 impl Message for IngestRequestMessage<'_> {}
@@ -103,15 +169,52 @@ impl Display for IngestRequestMessage<'_> {
             "{}",
             match self {
                 Self::Push(collection, bucket, object, text) =>
-                    format!("PUSH {} {} {} \"{}\"", collection, bucket, object, text),
+                    format!("PUSH {} {} {} \"{}\"", collection, bucket, object, escape(text)),
+                Self::Pop(collection, bucket, object, text) =>
+                    format!("POP {} {} {} \"{}\"", collection, bucket, object, escape(text)),
+                Self::Count(collection, None, _) => format!("COUNT {}", collection),
+                Self::Count(collection, Some(bucket), None) =>
+                    format!("COUNT {} {}", collection, bucket),
+                Self::Count(collection, Some(bucket), Some(object)) =>
+                    format!("COUNT {} {} {}", collection, bucket, object),
                 Self::Flushc(collection) => format!("FLUSHC {}", collection),
+                Self::Flushb(collection, bucket) => format!("FLUSHB {} {}", collection, bucket),
+                Self::Flusho(collection, bucket, object) =>
+                    format!("FLUSHO {} {} {}", collection, bucket, object),
             }
         )
     }
 }
 
+/// Optional parts of a QUERY. Sonic applies its configured default limit when none is given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryOptions<'a> {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Locale code such as `eng`, for stop words and stemming
+    pub lang: Option<&'a str>,
+}
+
+impl Display for QueryOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT({})", limit)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " OFFSET({})", offset)?;
+        }
+        if let Some(lang) = self.lang {
+            write!(f, " LANG({})", lang)?;
+        }
+        Ok(())
+    }
+}
+
 pub enum SearchRequestMessage<'a> {
-    Query(&'a str, &'a str, &'a str),
+    /// Collection, bucket, terms and options
+    Query(&'a str, &'a str, &'a str, QueryOptions<'a>),
+    /// Collection, bucket, partial word and the number of completions wanted
+    Suggest(&'a str, &'a str, &'a str, usize),
 }
 
 impl Display for SearchRequestMessage<'_> {
@@ -120,8 +223,10 @@ impl Display for SearchRequestMessage<'_> {
             f,
             "{}",
             match self {
-                Self::Query(collection, bucket, query) =>
-                    format!("QUERY {} {} \"{}\"", collection, bucket, query),
+                Self::Query(collection, bucket, query, options) =>
+                    format!("QUERY {} {} \"{}\"{}", collection, bucket, escape(query), options),
+                Self::Suggest(collection, bucket, word, limit) =>
+                    format!("SUGGEST {} {} \"{}\" LIMIT({})", collection, bucket, escape(word), limit),
             }
         )
     }
@@ -129,18 +234,56 @@ impl Display for SearchRequestMessage<'_> {
 
 impl Message for SearchRequestMessage<'_> {}
 
+/// Administrative actions run by the server.
+pub enum Trigger<'a> {
+    /// Writes pending index changes to disk
+    Consolidate,
+    /// Backs the stores up to a path on the server
+    Backup(&'a str),
+    /// Restores the stores from a backup path on the server
+    Restore(&'a str),
+}
+
+impl Display for Trigger<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Consolidate => write!(f, "consolidate"),
+            Self::Backup(path) => write!(f, "backup {}", path),
+            Self::Restore(path) => write!(f, "restore {}", path),
+        }
+    }
+}
+
+pub enum ControlRequestMessage<'a> {
+    Trigger(Trigger<'a>),
+    Info,
+}
+
+impl Display for ControlRequestMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Trigger(action) => write!(f, "TRIGGER {}", action),
+            Self::Info => write!(f, "INFO"),
+        }
+    }
+}
+
+impl Message for ControlRequestMessage<'_> {}
+
 #[derive(Debug)]
 pub enum EventKind {
     Query,
     Suggest,
 }
 
-impl From<&str> for EventKind {
-    fn from(s: &str) -> Self {
+impl TryFrom<&str> for EventKind {
+    type Error = ResponseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
         match s {
-            "QUERY" => Self::Query,
-            "SUGGEST" => Self::Suggest,
-            &_ => unreachable!(),
+            "QUERY" => Ok(Self::Query),
+            "SUGGEST" => Ok(Self::Suggest),
+            _ => Err(ResponseError::UnknownEvent(String::from(s))),
         }
     }
 }
@@ -153,78 +296,331 @@ pub struct Event {
     raw: String,
 }
 
+/// What the server announces once a channel is started, e.g.
+/// `STARTED ingest protocol(1) buffer(20000)`
+#[derive(Debug, Default)]
+pub struct Started {
+    pub mode: String,
+    pub protocol: Option<u32>,
+    /// Longest line the server accepts, in bytes
+    pub buffer: Option<usize>,
+}
+
+/// Parses `name(value)` parameters such as `buffer(20000)`.
+fn parameters(tokens: &[String]) -> Vec<(String, String)> {
+    tokens
+        .iter()
+        .filter_map(|t| {
+            let open = t.find('(')?;
+            let value = t[open + 1..].trim_end_matches(')');
+            Some((String::from(&t[..open]), String::from(value)))
+        })
+        .collect()
+}
+
+/// Escapes text for a quoted argument. A raw line break would end the command. Backslashes are
+/// doubled too: sonic takes a quote after an odd run of backslashes as escaped, and its unescape
+/// reads `\\` as one backslash but drops whatever follows a lone one.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Splits text between words into pieces that are at most `max` bytes once escaped. Words
+/// longer than that are split between characters.
+fn chunk_text(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+    let mut chunks = Vec::new();
+    let mut chunk = String::default();
+    let mut chunk_len = 0;
+    for word in text.split_whitespace() {
+        let word_len = escape(word).len();
+        if chunk_len > 0 && chunk_len + 1 + word_len <= max {
+            chunk.push(' ');
+            chunk.push_str(word);
+            chunk_len += 1 + word_len;
+            continue;
+        }
+        if chunk_len > 0 {
+            chunks.push(std::mem::replace(&mut chunk, String::default()));
+            chunk_len = 0;
+        }
+        if word_len <= max {
+            chunk.push_str(word);
+            chunk_len = word_len;
+            continue;
+        }
+        for ch in word.chars() {
+            let ch_len = escape(&ch.to_string()).len();
+            if chunk_len + ch_len > max && chunk_len > 0 {
+                chunks.push(std::mem::replace(&mut chunk, String::default()));
+                chunk_len = 0;
+            }
+            chunk.push(ch);
+            chunk_len += ch_len;
+        }
+    }
+    if chunk_len > 0 {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
 #[derive(Debug)]
 pub struct Pending {
     id: String,
     raw: String,
 }
 
-impl From<String> for ResponseMessage {
-    fn from(s: String) -> Self {
-        let mut tokens: Vec<String> = s.split(' ').map(String::from).collect();
-        match &*tokens.remove(0) {
+impl TryFrom<String> for ResponseMessage {
+    type Error = ResponseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let mut tokens = s.split_whitespace().map(String::from);
+        let verb = tokens.next().ok_or(ResponseError::Empty)?;
+        let mut tokens: Vec<String> = tokens.collect();
+        let malformed = || ResponseError::Malformed(s.clone());
+        Ok(match &*verb {
             "ENDED" => ResponseMessage::Ended,
             "CONNECTED" => ResponseMessage::Connected,
-            "STARTED" => ResponseMessage::Started,
+            "STARTED" => {
+                let params = parameters(&tokens);
+                let param = |name: &str| {
+                    params
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default()
+                };
+                ResponseMessage::Started(Started {
+                    mode: tokens.first().cloned().unwrap_or_default(),
+                    protocol: param("protocol").parse().ok(),
+                    buffer: param("buffer").parse().ok(),
+                })
+            }
             "PENDING" => ResponseMessage::Pending(Pending {
-                id: tokens.remove(0),
+                id: tokens.pop().ok_or_else(malformed)?,
                 raw: s,
             }),
             "EVENT" => {
-                let kind = EventKind::from(&*tokens.remove(0));
-                let id = tokens.remove(0);
+                if tokens.len() < 2 {
+                    return Err(malformed());
+                }
+                let mut tokens = tokens.into_iter();
+                let kind = EventKind::try_from(&*tokens.next().unwrap_or_default())?;
+                let id = tokens.next().unwrap_or_default();
                 ResponseMessage::Event(Event {
                     kind,
                     id,
-                    data: tokens,
+                    data: tokens.collect(),
                     raw: s,
                 })
             }
             "PONG" => ResponseMessage::Pong,
-            "RESULT" => ResponseMessage::Result,
+            // Either a count or INFO stats like `uptime(3600) clients_connected(2)`
+            "RESULT" => match tokens.first().map(|n| n.parse()) {
+                Some(Ok(n)) => ResponseMessage::Result(n),
+                _ => ResponseMessage::Info(parameters(&tokens)),
+            },
             "OK" => ResponseMessage::Ok,
-            "ERR" => ResponseMessage::Err,
-            s => unreachable!(s),
+            "ERR" => ResponseMessage::Err(tokens.join(" ")),
+            _ => return Err(ResponseError::UnknownResponse(s)),
+        })
+    }
+}
+
+/// Reply to a request as passed back from the connection's thread
+type Reply = Result<String, ClientError>;
+
+/// A caller waiting on sonic
+#[derive(Debug)]
+struct Waiter {
+    /// Taken once answered, e.g. with a timeout while the reply is still due
+    tx: Option<oneshot::Sender<Reply>>,
+    /// When the caller stops waiting
+    deadline: Instant,
+}
+
+impl Waiter {
+    fn answer(&mut self, res: Reply) {
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(res);
         }
     }
+
+    fn is_waiting(&self) -> bool {
+        self.tx.as_ref().map_or(false, |tx| !tx.is_canceled())
+    }
 }
 
-#[derive(Derivative)]
-#[derivative(Debug)]
+/// Callers waiting on sonic. Every request gets an immediate reply, in the order requests were
+/// written. Queries and suggestions are answered `PENDING <id>` first and their results come
+/// later as `EVENT <kind> <id> ...`, possibly out of order.
+#[derive(Debug, Default)]
+struct Waiters {
+    /// Waiting for an immediate reply, oldest request first
+    replies: VecDeque<Waiter>,
+    /// Waiting for the event with a marker id
+    events: HashMap<String, Waiter>,
+}
+
+impl Waiters {
+    /// Passes an immediate reply to the oldest request.
+    fn reply(&mut self, res: String) {
+        match self.replies.pop_front() {
+            Some(mut waiter) => waiter.answer(Ok(res)),
+            None => warn!("no request waiting for {}", res),
+        }
+    }
+
+    /// Fails every request in flight, e.g. when the connection drops.
+    fn lose_all(&mut self) {
+        for waiter in self.replies.iter_mut().chain(self.events.values_mut()) {
+            waiter.answer(Err(ClientError::ConnectionLost));
+        }
+        *self = Self::default();
+    }
+
+    /// Times out requests past their deadline. Those still due an immediate reply keep their
+    /// place so that the late reply goes nowhere.
+    fn expire(&mut self, now: Instant) {
+        for waiter in self.replies.iter_mut() {
+            if waiter.deadline <= now {
+                waiter.answer(Err(ClientError::Timeout));
+            }
+        }
+        self.events.retain(|_, waiter| {
+            if waiter.deadline <= now {
+                waiter.answer(Err(ClientError::Timeout));
+            }
+            waiter.is_waiting()
+        });
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.replies
+            .iter()
+            .chain(self.events.values())
+            .filter(|w| w.is_waiting())
+            .map(|w| w.deadline)
+            .min()
+    }
+}
+
+/// Something for the connection's thread to do
+#[derive(Debug)]
+enum Command {
+    /// Write a request and pass its reply to the waiter
+    Send(String, Waiter),
+    Quit,
+}
+
+#[derive(Debug)]
 struct InnerClient {
-    results_rx: Receiver<String>,
-    tx: Sender<String>,
+    /// Taken when the client goes away, which ends the connection's thread
+    tx: Option<Sender<Command>>,
+    /// Wakes the connection's thread to pick up commands
+    waker: Waker,
     event_loop: Option<JoinHandle<()>>,
-    started: Arc<(Mutex<bool>, Condvar)>,
+    request_timeout: Duration,
+    /// Longest line the server accepts, as announced when the channel started
+    buffer: Arc<Mutex<usize>>,
+}
+
+impl InnerClient {
+    /// Hands a command to the connection's thread.
+    fn submit(&self, command: Command) -> Result<(), ClientError> {
+        let tx = self.tx.as_ref().ok_or(ClientError::ProcessingError)?;
+        tx.send(command).map_err(|_| ClientError::ProcessingError)?;
+        self.waker.wake().map_err(|_| ClientError::ProcessingError)
+    }
+}
+
+impl Drop for InnerClient {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        let _ = self.waker.wake();
+    }
 }
 
-/// Client accepts queries / commands destined for sonic server and
-/// dispatches via underlying thread.
+/// Talks to sonic without blocking. A connection has one thread, which sleeps on the socket
+/// until there is something to read, write or time out, and completes the futures returned
+/// here as replies come in. Clones share the connection.
 ///
 /// # Examples
 ///
+/// ```ignore
+/// use futures::executor::block_on;
+///
+/// let client = AsyncClient::connect(ClientOptions::default()).expect("Failed to connect");
+/// let opts = QueryOptions::default();
+/// let ids = block_on(client.query("mycollection", "mybucket", "Find this text", opts))
+///     .expect("Query failed");
+/// for id in ids {
+///     // Do something interesting
+/// }
 /// ```
+#[derive(Clone, Debug)]
+pub struct AsyncClient {
+    inner: Arc<Mutex<InnerClient>>,
+}
+
+/// Blocking client for callers that aren't async, waiting on an `AsyncClient`.
+///
+/// # Examples
+///
+/// ```ignore
 /// let mut client = Client::connect(ClientOptions::default()).expect("Failed to connect");
 /// let col = "mycollection";
 /// let bkt = "mybucket";
 /// let qry = "Find this text";
-/// let res = client.send(SearchRequestMessage::Query(col, bkt, qry)).expect("Query failed");
-/// for r in res.data {
-///     // Do something interesting
+/// let opts = QueryOptions::default();
+/// let res = client.send(SearchRequestMessage::Query(col, bkt, qry, opts)).expect("Query failed");
+/// if let ResponseMessage::Event(evt) = *res {
+///     for id in evt.data {
+///         // Do something interesting
+///     }
 /// }
 /// let _ = client.disconnect();
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Derivative)]
+#[derivative(Debug)]
 pub struct Client {
-    inner: Arc<Mutex<InnerClient>>,
+    inner: AsyncClient,
+    /// Connections bulk indexing is spread across, starting with `inner`
+    pool: Vec<AsyncClient>,
+    /// Where the documents behind result ids are looked up
+    #[derivative(Debug = "ignore")]
+    store: Option<Arc<dyn DocumentStore>>,
 }
 
+/// Buckets searched, in the order their matches are listed
+const BUCKETS: [&str; 2] = ["name", "desc"];
+
 /// Settings for connecting to sonic
+#[derive(Clone)]
 pub struct ClientOptions<'a> {
     pub addr: SocketAddr,
     pub password: &'a str,
     pub mode: Mode,
+    /// How often to check the connection is alive. Keep this under sonic's `tcp_timeout`.
     pub ping_interval: Duration,
+    /// How long to wait for a PONG, or for STARTED after connecting, before giving up on the
+    /// connection
+    pub pong_timeout: Duration,
+    /// How long a request may wait for its reply, including any time spent reconnecting
+    pub request_timeout: Duration,
+    /// Wait before the first reconnect attempt, doubled after each failed attempt
+    pub reconnect_backoff: Duration,
 }
 
 impl Default for ClientOptions<'_> {
@@ -234,198 +630,602 @@ impl Default for ClientOptions<'_> {
             password: "SecretPassword",
             mode: Mode::Search,
             ping_interval: Duration::from_secs(5),
+            pong_timeout: Duration::from_secs(15),
+            request_timeout: Duration::from_secs(10),
+            reconnect_backoff: Duration::from_millis(250),
         }
     }
 }
 
-impl Client {
-    pub fn connect(options: ClientOptions) -> Result<Self, Box<dyn Error>> {
-        // tcp
-        let (tcp_tx, tcp_rx) = channel();
-
-        let (tx, rx): (Sender<String>, Receiver<String>) = channel();
-
-        let (results_tx, results_rx) = channel();
-
-        let mut worker = TcpStreamWorker::connect(options.addr, tcp_tx)?;
+/// Owns the connection: writes requests, routes replies to waiting callers, and reconnects when
+/// the connection dies. Runs on the connection's thread.
+///
+/// The futures executors can run async callers but have no way to wait on a socket, so the
+/// connection gets a thread of its own blocked in mio's poll. That's one thread per connection:
+/// one for searching and at most `--sonic-connections` while indexing.
+struct EventLoop {
+    addr: SocketAddr,
+    /// The START line, replayed after reconnecting
+    start: String,
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    reconnect_backoff: Duration,
+    poll: Poll,
+    connection: Option<Connection>,
+    /// Requests from callers
+    rx: Receiver<Command>,
+    waiters: Waiters,
+    /// Requests held until the channel has started
+    unsent: VecDeque<(String, Waiter)>,
+    /// Whether START has been answered on the current connection
+    started: bool,
+    buffer: Arc<Mutex<usize>>,
+    last_ping: Instant,
+    /// When the PING still waiting for its PONG was sent. START counts as a ping until
+    /// STARTED comes back.
+    ping_sent: Option<Instant>,
+    /// Wait before the next reconnect attempt
+    backoff: Duration,
+    /// When to try connecting again while disconnected
+    retry_at: Option<Instant>,
+    /// When to stop waiting for the server to end the connection after QUIT
+    quit_at: Option<Instant>,
+}
 
-        let pair = Arc::new((Mutex::new(false), Condvar::new()));
-        let pair2 = pair.clone();
+impl EventLoop {
+    fn run(mut self) {
+        let mut events = Events::with_capacity(16);
+        loop {
+            let timeout = self
+                .next_wakeup()
+                .map(|at| at.saturating_duration_since(Instant::now()));
+            if let Err(e) = self.poll.poll(&mut events, timeout) {
+                if e.kind() != io::ErrorKind::Interrupted {
+                    error!("polling the sonic connection failed: {}", e);
+                    break;
+                }
+            }
+            // Readiness is only a hint: the socket is non-blocking so everything is tried
+            // whichever event woke us
+            if !self.take_commands() {
+                break;
+            }
+            if self.connection.is_some() && !(self.receive() && self.ping()) {
+                if self.quit_at.is_some() {
+                    break;
+                }
+                self.lost();
+            }
+            let now = Instant::now();
+            if self.quit_at.is_none() && self.retry_at.map_or(false, |at| at <= now) {
+                self.reconnect();
+            }
+            if !self.flush() {
+                self.lost();
+            }
+            self.waiters.expire(now);
+            for (_, waiter) in self.unsent.iter_mut().filter(|(_, w)| w.deadline <= now) {
+                waiter.answer(Err(ClientError::Timeout));
+            }
+            self.unsent.retain(|(_, waiter)| waiter.is_waiting());
+            if self.quit_at.map_or(false, |at| at <= now) {
+                break;
+            }
+        }
+        if let Some(connection) = self.connection.take() {
+            connection.shutdown(self.poll.registry());
+        }
+        trace!("Wrapping up");
+    }
 
-        let mut last_ping = std::time::Instant::now();
+    /// The soonest something needs doing other than reading or writing
+    fn next_wakeup(&self) -> Option<Instant> {
+        let ping = match (&self.connection, self.ping_sent) {
+            (None, _) => None,
+            (Some(_), Some(sent)) => Some(sent + self.pong_timeout),
+            (Some(_), None) => Some(self.last_ping + self.ping_interval),
+        };
+        let unsent = self.unsent.iter().map(|(_, w)| w.deadline).min();
+        [ping, self.retry_at, self.quit_at, unsent, self.waiters.next_deadline()]
+            .iter()
+            .filter_map(|at| *at)
+            .min()
+    }
 
-        let ping_interval = options.ping_interval;
+    /// Takes requests from callers. Returns false once every client is gone.
+    fn take_commands(&mut self) -> bool {
+        loop {
+            match self.rx.try_recv() {
+                Ok(Command::Send(msg, waiter)) => self.unsent.push_back((msg, waiter)),
+                Ok(Command::Quit) => {
+                    self.started = false;
+                    self.quit_at = Some(Instant::now());
+                    if let Some(connection) = &mut self.connection {
+                        connection.write(&format!("{}\r\n", RequestMessage::Quit));
+                        self.quit_at = Some(Instant::now() + QUIT_TIMEOUT);
+                    }
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
 
-        // Communicates with worker
-        let event_loop = spawn(move || {
-            'event_loop: loop {
-                // Control signals??
-                {}
-                let now = std::time::Instant::now();
-                if now > last_ping + ping_interval {
-                    let _ = worker.write(&format!("{}\r\n", RequestMessage::Ping));
-                    last_ping = now;
+    /// Writes requests once the channel has started, queueing their waiters in the same order.
+    /// Returns false if the connection has failed.
+    fn flush(&mut self) -> bool {
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => return true,
+        };
+        if self.started {
+            while let Some((msg, waiter)) = self.unsent.pop_front() {
+                if waiter.is_waiting() {
+                    connection.write(&msg);
+                    self.waiters.replies.push_back(waiter);
                 }
+            }
+        }
+        if let Err(e) = connection.flush() {
+            warn!("error writing to sonic: {}", e);
+            return false;
+        }
+        true
+    }
 
-                // Sends all pending messages in queue
-                loop {
-                    match rx.recv_timeout(Duration::from_millis(10)) {
-                        Ok(msg) => {
-                            debug!("[client-send] writing: {}", msg);
-                            if let Err(err) = worker.write(&msg) {
-                                trace!("[client-send] error writing: {}", err);
-                            }
-                        }
-                        Err(err) => match err {
-                            RecvTimeoutError::Timeout => {
-                                trace!("[client-send] (timeout)    {}", err);
-                                break;
-                            }
-                            RecvTimeoutError::Disconnected => {
-                                trace!("[client-send] (disconnect) {}", err);
-                                break 'event_loop;
-                            }
-                        },
-                    }
+    /// Sends a PING when one is due. Returns false if the last one went unanswered.
+    fn ping(&mut self) -> bool {
+        match self.ping_sent {
+            Some(sent) if sent.elapsed() >= self.pong_timeout => {
+                warn!("sonic stopped answering pings");
+                return false;
+            }
+            Some(_) => {}
+            None if self.quit_at.is_none() && self.last_ping.elapsed() >= self.ping_interval => {
+                if let Some(connection) = &mut self.connection {
+                    connection.write(&format!("{}\r\n", RequestMessage::Ping));
                 }
+                self.last_ping = Instant::now();
+                self.ping_sent = Some(self.last_ping);
+            }
+            None => {}
+        }
+        true
+    }
 
-                // Receives all pending results
-                loop {
-                    match tcp_rx.recv_timeout(Duration::from_millis(10)) {
-                        Ok(res) => {
-                            debug!("[client-recv] result: {}", res);
-                            let _ = match ResponseMessage::from(res.clone()) {
-                                ResponseMessage::Started => {
-                                    debug!("Got Started");
-                                    let (lock, cvar) = &*pair;
-                                    let mut started = lock.lock().unwrap();
-                                    *started = true;
-                                    cvar.notify_all();
-                                    continue;
-                                }
-                                ResponseMessage::Connected => continue,
-                                ResponseMessage::Ended => break,
-                                ResponseMessage::Event(_) => results_tx.send(res),
-                                ResponseMessage::Pending(_) => continue,
-                                ResponseMessage::Result => results_tx.send(res),
-                                ResponseMessage::Ok => results_tx.send(res),
-                                ResponseMessage::Pong => continue,
-                                ResponseMessage::Err => {
-                                    error!("{}", res);
-                                    let _ = results_tx.send(res);
-                                    break;
-                                }
-                            };
-                        }
-                        Err(err) => match err {
-                            RecvTimeoutError::Timeout => {
-                                trace!("[client-recv] (timeout)    {}", err);
-                                break;
-                            }
-                            RecvTimeoutError::Disconnected => {
-                                trace!("[client-recv] (disconnect) {}", err);
-                                break 'event_loop;
-                            }
-                        },
+    /// Routes everything read from the socket. Returns false when the connection has ended.
+    fn receive(&mut self) -> bool {
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => return false,
+        };
+        let lines = match connection.read_lines() {
+            Ok(lines) => lines,
+            Err(e) => {
+                warn!("error reading from sonic: {}", e);
+                return false;
+            }
+        };
+        let closed = connection.is_closed();
+        for res in lines {
+            if !self.route(res) {
+                return false;
+            }
+        }
+        if closed && self.quit_at.is_none() {
+            warn!("sonic closed the connection");
+        }
+        !closed
+    }
+
+    /// Passes a line from sonic on to whoever is waiting for it. Returns false on ENDED.
+    fn route(&mut self, res: String) -> bool {
+        debug!("[client-recv] result: {}", res);
+        let msg = match ResponseMessage::try_from(res.clone()) {
+            Ok(msg) => msg,
+            Err(e) => {
+                // Let whoever is waiting see the error rather than hang
+                warn!("{}", e);
+                self.waiters.reply(res);
+                return true;
+            }
+        };
+        match msg {
+            ResponseMessage::Started(started) => {
+                debug!("Got Started {:?}", started);
+                *self.buffer.lock().unwrap() = started.buffer.unwrap_or(DEFAULT_BUFFER);
+                self.started = self.quit_at.is_none();
+                self.last_ping = Instant::now();
+                self.ping_sent = None;
+                self.backoff = self.reconnect_backoff;
+            }
+            ResponseMessage::Connected => {}
+            ResponseMessage::Ended => return false,
+            ResponseMessage::Pending(pending) => {
+                if let Some(waiter) = self.waiters.replies.pop_front() {
+                    if waiter.is_waiting() {
+                        self.waiters.events.insert(pending.id, waiter);
                     }
                 }
             }
-            if let Err(err) = worker.disconnect() {
-                trace!("Error disconnecting TcpStreamWorker {}", err);
+            ResponseMessage::Event(event) => match self.waiters.events.remove(&event.id) {
+                Some(mut waiter) => waiter.answer(Ok(res)),
+                None => warn!("no request waiting for event {}", event.id),
+            },
+            ResponseMessage::Result(_) | ResponseMessage::Info(_) | ResponseMessage::Ok => {
+                self.waiters.reply(res)
             }
-            trace!("Wrapping up");
-        });
+            ResponseMessage::Pong => self.ping_sent = None,
+            ResponseMessage::Err(_) => {
+                error!("{}", res);
+                self.waiters.reply(res);
+            }
+        }
+        true
+    }
 
-        let client = Client {
-            inner: Arc::new(Mutex::new(InnerClient {
-                event_loop: Some(event_loop),
-                tx,
-                results_rx,
-                started: pair2,
-            })),
-        };
+    /// Drops the dead connection and schedules a reconnect, backing off further each time until
+    /// a channel starts again. Requests in flight fail; those not yet written wait for the new
+    /// connection.
+    fn lost(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.shutdown(self.poll.registry());
+        }
+        self.started = false;
+        self.waiters.lose_all();
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
 
-        client.write(&RequestMessage::Start(options.mode, options.password).to_string())?;
-        Ok(client)
-    }
-
-    /// Sends message to TcpStreamWorker and does not wait or block.
-    // pub fn send_nowait(&self, m: impl Message) -> Result<(), Box<dyn Error>> {
-    //     // Wait for worker to start up if necessary
-    //     {
-    //         let (lock, cvar) = &*self.inner.lock().unwrap().started;
-    //         let mut started = lock.lock().unwrap();
-    //         while !*started {
-    //             debug!("send called but waiting for start");
-    //             started = cvar.wait(started).unwrap();
-    //         }
-    //     }
-    //
-    //     // Send message to worker
-    //     self.write(&format!("{}\r\n", m))?;
-    //
-    //     // Await response
-    //     Ok(())
-    // }
-    //
-    /// Sends message to TcpStreamWorker and awaits response. Messages are
-    /// processed in-order of receipt.
-    pub fn send(&self, m: impl Message) -> Result<Box<ResponseMessage>, Box<dyn Error>> {
-        // Wait for worker to start up if necessary
-        {
-            let (lock, cvar) = &*self.inner.lock().unwrap().started;
-            let mut started = lock.lock().unwrap();
-            while !*started {
-                debug!("send called but waiting for start");
-                started = cvar.wait(started).unwrap();
+    /// Starts connecting again without waiting for the server. The START line goes out once the
+    /// connection is made, and it counts as an unanswered ping until STARTED comes back.
+    fn reconnect(&mut self) {
+        match Connection::connect(self.addr, self.poll.registry(), SOCKET) {
+            Ok(mut connection) => {
+                info!("reconnecting to sonic at {}", self.addr);
+                connection.write(&self.start);
+                self.connection = Some(connection);
+                self.retry_at = None;
+                self.ping_sent = Some(Instant::now());
+            }
+            Err(e) => {
+                warn!("failed to reconnect to sonic, retrying in {:?}: {}", self.backoff, e);
+                self.lost();
             }
         }
+    }
+}
+
+impl AsyncClient {
+    pub fn connect(options: ClientOptions) -> Result<Self, Box<dyn Error>> {
+        let (tx, rx) = channel();
+        let poll = Poll::new()?;
+        let waker = Waker::new(poll.registry(), WAKER)?;
+        let mut connection = Connection::connect_timeout(options.addr, poll.registry(), SOCKET)?;
+        let start = format!("{}\r\n", RequestMessage::Start(options.mode, options.password));
+        connection.write(&start);
+        let buffer = Arc::new(Mutex::new(DEFAULT_BUFFER));
 
-        // Send message to worker
-        let _ = self.write(&format!("{}\r\n", m));
+        let event_loop = EventLoop {
+            addr: options.addr,
+            start,
+            ping_interval: options.ping_interval,
+            pong_timeout: options.pong_timeout,
+            reconnect_backoff: options.reconnect_backoff,
+            poll,
+            connection: Some(connection),
+            rx,
+            waiters: Waiters::default(),
+            unsent: VecDeque::new(),
+            started: false,
+            buffer: Arc::clone(&buffer),
+            last_ping: Instant::now(),
+            ping_sent: Some(Instant::now()),
+            backoff: options.reconnect_backoff,
+            retry_at: None,
+            quit_at: None,
+        };
 
-        // Await response
-        Ok({
-            let inner = self.inner.lock().unwrap();
-            let res: String = inner.results_rx.recv()?;
-            Box::new(ResponseMessage::from(res))
+        Ok(Self {
+            inner: Arc::new(Mutex::new(InnerClient {
+                tx: Some(tx),
+                waker,
+                event_loop: Some(spawn(move || event_loop.run())),
+                request_timeout: options.request_timeout,
+                buffer,
+            })),
         })
     }
 
-    /// Writes message to TcpStreamWorker. Does not guarantee delivery
-    /// or response. Does not block.
-    fn write(&self, msg: &str) -> Result<(), Box<dyn Error>> {
-        trace!("write");
-        if self
-            .inner
-            .lock()
-            .unwrap()
-            .tx
-            .send(String::from(msg))
-            .is_ok()
+    /// Sends a request and resolves to its reply. Requests made before the channel has started,
+    /// or while reconnecting, are held until it has.
+    pub async fn send(&self, m: impl Message) -> Result<Box<ResponseMessage>, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
         {
-            Ok(())
-        } else {
-            Err(Box::new(ClientError::ProcessingError))
+            let inner = self.inner.lock().unwrap();
+            let waiter = Waiter {
+                tx: Some(tx),
+                deadline: Instant::now() + inner.request_timeout,
+            };
+            inner.submit(Command::Send(format!("{}\r\n", m), waiter))?;
+        }
+
+        // The sender only goes away without answering if the connection's thread has ended
+        let res = rx.await.unwrap_or(Err(ClientError::ConnectionLost))?;
+        match ResponseMessage::try_from(res)? {
+            ResponseMessage::Err(reason) => Err(Box::new(ResponseError::Server(reason))),
+            msg => Ok(Box::new(msg)),
         }
     }
 
-    /// Terminates the event loop and the underlying TcpStream.
-    /// Before the event loop thread ends it calls disconnect
-    /// on the TcpStream worker.
+    /// Sends a request answered with `PENDING` and then an event, and returns the event data.
+    async fn send_event(&self, m: impl Message) -> Result<Vec<String>, Box<dyn Error>> {
+        match *self.send(m).await? {
+            ResponseMessage::Event(evt) => Ok(evt.data),
+            res => Err(Box::new(ClientError::UnexpectedResponse(format!("{:?}", res)))),
+        }
+    }
+
+    /// Queries a bucket for the ids of matching objects.
+    pub async fn query(
+        &self,
+        col: &str,
+        bucket: &str,
+        terms: &str,
+        options: QueryOptions<'_>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        self.send_event(SearchRequestMessage::Query(col, bucket, terms, options)).await
+    }
+
+    /// Completes a partial word from the words pushed to a bucket.
+    pub async fn suggest(
+        &self,
+        col: &str,
+        bucket: &str,
+        word: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        self.send_event(SearchRequestMessage::Suggest(col, bucket, word, limit)).await
+    }
+
+    /// Indexes text for an object, split over several PUSHes when it won't fit in sonic's
+    /// buffer.
+    pub async fn push(
+        &self,
+        col: &str,
+        bucket: &str,
+        object: &str,
+        text: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let max = self.max_text(&IngestRequestMessage::Push(col, bucket, object, ""));
+        for chunk in chunk_text(text, max) {
+            match *self.send(IngestRequestMessage::Push(col, bucket, object, &chunk)).await? {
+                ResponseMessage::Ok => {}
+                res => return Err(Box::new(ClientError::UnexpectedResponse(format!("{:?}", res)))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Room left for text in a request, given the request with empty text.
+    fn max_text(&self, empty: &dyn Message) -> usize {
+        let buffer = *self.inner.lock().unwrap().buffer.lock().unwrap();
+        buffer.saturating_sub(format!("{}\r\n", empty).len())
+    }
+
+    /// Sends QUIT and waits for the connection's thread to end.
     pub fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
         trace!("disconnect");
         let mut inner = self.inner.lock().unwrap();
-        {
-            let _ = inner.tx.send(String::from("QUIT\r\n"));
-            std::thread::sleep(Duration::from_secs(1));
+        inner.submit(Command::Quit)?;
+        if let Some(event_loop) = inner.event_loop.take() {
+            let _ = event_loop.join();
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    pub fn connect(options: ClientOptions) -> Result<Self, Box<dyn Error>> {
+        Self::connect_pool(options, 1)
+    }
+
+    /// Opens several connections that `index_bulk` spreads its pushes across. Other requests
+    /// use the first.
+    pub fn connect_pool(
+        options: ClientOptions,
+        connections: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let pool = (0..connections.max(1))
+            .map(|_| AsyncClient::connect(options.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Client {
+            inner: pool[0].clone(),
+            pool,
+            store: None,
+        })
+    }
+
+    /// Sets the store that result ids are hydrated from. Needed for `Indexer::query`.
+    pub fn with_store(mut self, store: impl DocumentStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// The async client underneath, sharing this client's connection
+    pub fn as_async(&self) -> &AsyncClient {
+        &self.inner
+    }
+
+    /// Sends a request and waits for its reply. Safe to call from several threads at once;
+    /// each caller gets the reply to its own request.
+    pub fn send(&self, m: impl Message) -> Result<Box<ResponseMessage>, Box<dyn Error>> {
+        block_on(self.inner.send(m))
+    }
+
+    /// Sends QUIT and waits for the connections to close.
+    pub fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        for client in &mut self.pool {
+            client.disconnect()?;
+        }
+        Ok(())
+    }
+
+    /// Sends a request answered with `RESULT <n>` and returns the count.
+    fn send_count(&self, m: impl Message) -> Result<usize, Box<dyn Error>> {
+        match *self.send(m)? {
+            ResponseMessage::Result(n) => Ok(n),
+            res => Err(Box::new(ClientError::UnexpectedResponse(format!("{:?}", res)))),
+        }
+    }
+
+    /// Removes the words of `text` from an object. Returns the number of words removed.
+    pub fn pop(
+        &self,
+        col: &str,
+        bucket: &str,
+        object: &str,
+        text: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.send_count(IngestRequestMessage::Pop(col, bucket, object, text))
+    }
+
+    /// Counts the buckets in a collection, the objects in a bucket or the words in an object.
+    pub fn count(
+        &self,
+        col: &str,
+        bucket: Option<&str>,
+        object: Option<&str>,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.send_count(IngestRequestMessage::Count(col, bucket, object))
+    }
+
+    /// Empties a bucket. Returns the number of objects flushed.
+    pub fn flush_bucket(&self, col: &str, bucket: &str) -> Result<usize, Box<dyn Error>> {
+        self.send_count(IngestRequestMessage::Flushb(col, bucket))
+    }
+
+    /// Removes an object from a bucket. Returns the number of words flushed.
+    pub fn flush_object(
+        &self,
+        col: &str,
+        bucket: &str,
+        object: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.send_count(IngestRequestMessage::Flusho(col, bucket, object))
+    }
+
+    /// Runs a TRIGGER on a control channel connection.
+    pub fn trigger(&self, action: Trigger) -> Result<(), Box<dyn Error>> {
+        match *self.send(ControlRequestMessage::Trigger(action))? {
+            ResponseMessage::Ok => Ok(()),
+            res => Err(Box::new(ClientError::UnexpectedResponse(format!("{:?}", res)))),
         }
+    }
 
-        // Waits for event loop thread to terminate
-        let _ = inner.event_loop.take().unwrap().join();
+    /// Reads server statistics on a control channel connection.
+    pub fn info(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        match *self.send(ControlRequestMessage::Info)? {
+            ResponseMessage::Info(stats) => Ok(stats),
+            res => Err(Box::new(ClientError::UnexpectedResponse(format!("{:?}", res)))),
+        }
+    }
 
+    /// Replaces what is indexed for a document, e.g. after it changes in the database.
+    pub fn reindex<T: Index>(&self, idx: Box<T>) -> Result<(), Box<dyn Error>> {
+        let mut flushed: Vec<(String, String)> = Vec::new();
+        for (collection, bucket, object, _) in idx.tuples() {
+            if !flushed.contains(&(collection.clone(), bucket.clone())) {
+                self.flush_object(&collection, &bucket, &object)?;
+                flushed.push((collection, bucket));
+            }
+        }
+        self.index(idx)?;
         Ok(())
     }
+
+    /// Queries a single bucket for ids.
+    fn query_bucket(
+        &self,
+        col: &str,
+        bucket: &str,
+        query: &str,
+        options: QueryOptions,
+    ) -> Result<Vec<String>, IndexError> {
+        match block_on(self.inner.query(col, bucket, query, options)) {
+            Ok(ids) => {
+                debug!("done sonic query {:?}", ids);
+                Ok(ids)
+            }
+            Err(e) => {
+                error!("sonic query failed: {}", e);
+                Err(index_error(e))
+            }
+        }
+    }
+
+    /// Lists up to `limit` more ids after `cursor`, asking each bucket only for the next `limit`
+    /// of its matches.
+    fn next_ids(
+        &self,
+        col: &str,
+        query: &str,
+        cursor: &mut Cursor,
+        limit: usize,
+    ) -> Result<Vec<String>, IndexError> {
+        let mut ids = Vec::new();
+        while ids.len() < limit && cursor.bucket < BUCKETS.len() {
+            let options = QueryOptions {
+                limit: Some(limit),
+                offset: Some(cursor.bucket_offset),
+                ..QueryOptions::default()
+            };
+            let found = self.query_bucket(col, BUCKETS[cursor.bucket], query, options)?;
+            let exhausted = found.len() < limit;
+            for id in found {
+                if ids.len() == limit {
+                    break;
+                }
+                cursor.bucket_offset += 1;
+                if cursor.seen.insert(id.clone()) {
+                    ids.push(id);
+                }
+            }
+            if exhausted && ids.len() < limit {
+                cursor.bucket += 1;
+                cursor.bucket_offset = 0;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Looks up the documents for ids in the client's store.
+    fn hydrate(&self, col: &str, ids: Vec<String>) -> Result<Vec<(String, Vec<u8>)>, IndexError> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => {
+                error!("sonic client has no document store to look up results");
+                return Err(IndexError::ProcessingError);
+            }
+        };
+        // Ids in the "all" collection are already prefixed with their collection
+        let ids: Vec<String> = ids
+            .into_iter()
+            .map(|id| if id.contains(':') { id } else { format!("{}:{}", col, id) })
+            .collect();
+        store.documents(&ids)
+    }
+}
+
+/// Keeps the reason sonic gave when it refused a request. Anything else is a processing error.
+fn index_error(e: Box<dyn Error>) -> IndexError {
+    match e.downcast::<ResponseError>() {
+        Ok(e) => match *e {
+            ResponseError::Server(reason) => IndexError::Server(reason),
+            _ => IndexError::ProcessingError,
+        },
+        Err(_) => IndexError::ProcessingError,
+    }
 }
 
 impl Indexer for Client {
@@ -434,67 +1234,259 @@ impl Indexer for Client {
             let (collection, bucket, object, text) = t;
             trace!("indexing item {:?}", (&collection, &bucket, &object, &text));
 
-            if let Err(e) = self.send(IngestRequestMessage::Push(
-                &collection,
-                &bucket,
-                &object,
-                &text,
-            )) {
+            if let Err(e) = block_on(self.inner.push(&collection, &bucket, &object, &text)) {
                 error!("failed to index: {}", e);
-                return Err(IndexError::ProcessingError)
+                return Err(index_error(e))
             }
         }
         Ok(())
     }
+    /// Pipelines the pushes rather than waiting for each OK, keeping up to `PIPELINE_DEPTH`
+    /// in flight on each connection. Every push that fails is logged; the rest still go in, and
+    /// the first failure is returned.
     fn index_bulk<T: Index>(&self, curs: Vec<Box<T>>) -> Result<(), IndexError> {
-        for idx in curs {
-            if let Err(e) = self.index(idx) {
-                error!("failed to index: {}", e);
+        let tuples: Vec<(String, String, String, String)> =
+            curs.iter().flat_map(|idx| idx.tuples()).collect();
+        let pushes = tuples.iter().enumerate().map(|(i, (collection, bucket, object, text))| {
+            let client = &self.pool[i % self.pool.len()];
+            async move {
+                let res = client.push(collection, bucket, object, text).await;
+                (collection, bucket, object, res)
+            }
+        });
+        let results: Vec<_> = block_on(
+            stream::iter(pushes)
+                .buffer_unordered(PIPELINE_DEPTH * self.pool.len())
+                .collect(),
+        );
+
+        let mut failed = 0;
+        let mut first = None;
+        for (collection, bucket, object, res) in results {
+            if let Err(e) = res {
+                error!("failed to index {} {} {}: {}", collection, bucket, object, e);
+                failed += 1;
+                first = first.or_else(|| Some(index_error(e)));
             }
         }
-        Ok(())
+        info!("sonic acknowledged {} of {} pushes", tuples.len() - failed, tuples.len());
+        match first {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
     fn flush_all(&self, collection: &str) -> std::result::Result<(), IndexError> {
         if let Err(e) = self.send(IngestRequestMessage::Flushc(collection)) {
             error!("error encountered in flush_all: {}", e);
-            return Err(IndexError::ProcessingError)
+            return Err(index_error(e))
         }
         Ok(())
     }
 
+    /// Queries sonic for ids and looks the documents up in the client's store.
     fn query(&self, col: &str, query: &str) -> Result<Vec<(String, Vec<u8>)>, IndexError> {
-        unimplemented!("not implemented for sonic client");
+        let ids = self.query_ids(col, query)?;
+        self.hydrate(col, ids)
+    }
+
+    /// Pages through name matches followed by description matches, carrying on from `cursor`.
+    /// Sonic caps LIMIT, so buckets are paged with OFFSET rather than asked for everything up to
+    /// the page. Sonic doesn't count matches, so the total is only known once every bucket has
+    /// run out.
+    fn query_page(
+        &self,
+        col: &str,
+        query: &str,
+        cursor: &mut Cursor,
+        limit: usize,
+    ) -> Result<Page, IndexError> {
+        let ids = self.next_ids(col, query, cursor, limit)?;
+        let docs = self.hydrate(col, ids)?;
+        cursor.offset += docs.len();
+        let total = if cursor.bucket == BUCKETS.len() {
+            Some(cursor.offset)
+        } else {
+            None
+        };
+        Ok(Page { docs, total })
     }
 
+    /// Queries names first, falling back to descriptions when there are few name matches.
     fn query_ids(&self, col: &str, query: &str) -> Result<Vec<String>, IndexError> {
         trace!("querying sonic");
-        let mut ids = Vec::new();
-        let res = self.send(SearchRequestMessage::Query(
-            col,
-            "name",
-            query,
-        )).unwrap();
-        debug!("done sonic query {:?}", res);
+        let mut ids: Vec<String> = Vec::new();
+        for bucket in &["name", "desc"] {
+            if ids.len() >= RESULTS_MIN {
+                break;
+            }
+            for id in self.query_bucket(col, bucket, query, QueryOptions::default())? {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Suggests completions from the words pushed to the name bucket.
+    fn suggest(&self, col: &str, word: &str, limit: usize) -> Result<Vec<String>, IndexError> {
+        block_on(self.inner.suggest(col, "name", word, limit)).map_err(|e| {
+            error!("sonic suggest failed: {}", e);
+            index_error(e)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
 
-        match *res {
-            ResponseMessage::Event(evt) => ids.extend_from_slice(&evt.data),
-            _ => unreachable!(),
+    fn parse(line: &str) -> Result<ResponseMessage, ResponseError> {
+        ResponseMessage::try_from(String::from(line))
+    }
+
+    #[test]
+    fn parses_pending() {
+        match parse("PENDING Bt2m2gYa") {
+            Ok(ResponseMessage::Pending(p)) => assert_eq!(p.id, "Bt2m2gYa"),
+            res => panic!("unexpected {:?}", res),
         }
+        assert!(matches!(parse("PENDING"), Err(ResponseError::Malformed(_))));
+    }
 
-        if ids.len() < RESULTS_MIN {
-            let res = self.send(SearchRequestMessage::Query(
-                col,
-                "desc",
-                query,
-            )).unwrap();
-            debug!("done sonic query {:?}", res);
+    #[test]
+    fn parses_events() {
+        match parse("EVENT QUERY Bt2m2gYa spells:1 spells:2") {
+            Ok(ResponseMessage::Event(e)) => {
+                assert!(matches!(e.kind, EventKind::Query));
+                assert_eq!(e.id, "Bt2m2gYa");
+                assert_eq!(e.data, vec!["spells:1", "spells:2"]);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+        match parse("EVENT SUGGEST z98uDE0f") {
+            Ok(ResponseMessage::Event(e)) => {
+                assert!(matches!(e.kind, EventKind::Suggest));
+                assert!(e.data.is_empty());
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+        assert!(matches!(parse("EVENT PUSH x"), Err(ResponseError::UnknownEvent(_))));
+        assert!(matches!(parse("EVENT QUERY"), Err(ResponseError::Malformed(_))));
+    }
 
-            match *res {
-                ResponseMessage::Event(evt) => ids.extend_from_slice(&evt.data),
-                _ => unreachable!(),
+    #[test]
+    fn parses_errors_with_their_reason() {
+        match parse("ERR invalid_format(QUERY <collection> <bucket> \"<terms>\")") {
+            Ok(ResponseMessage::Err(reason)) => {
+                assert_eq!(reason, "invalid_format(QUERY <collection> <bucket> \"<terms>\")")
             }
+            res => panic!("unexpected {:?}", res),
         }
+        assert!(matches!(parse(""), Err(ResponseError::Empty)));
+        assert!(matches!(parse("HELLO"), Err(ResponseError::UnknownResponse(_))));
+    }
 
-        Ok(ids)
+    #[test]
+    fn server_errors_keep_their_reason() {
+        let e: Box<dyn Error> = Box::new(ResponseError::Server(String::from("query_error")));
+        match index_error(e) {
+            IndexError::Server(reason) => assert_eq!(reason, "query_error"),
+            e => panic!("unexpected {:?}", e),
+        }
+        let e: Box<dyn Error> = Box::new(ClientError::Timeout);
+        assert!(matches!(index_error(e), IndexError::ProcessingError));
+        assert_eq!(
+            IndexError::Server(String::from("query_error")).to_string(),
+            "Error from the index server: query_error"
+        );
+    }
+
+    /// Hands ids back as documents, so pages can be checked by id.
+    struct Ids;
+
+    impl DocumentStore for Ids {
+        fn documents(&self, ids: &[String]) -> Result<Vec<(String, Vec<u8>)>, IndexError> {
+            Ok(ids.iter().map(|id| (String::from("spell"), id.clone().into_bytes())).collect())
+        }
+    }
+
+    /// The number in a QUERY option like `LIMIT(3)`
+    fn option(tokens: &[&str], name: &str) -> usize {
+        tokens
+            .iter()
+            .find_map(|t| t.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')?.parse().ok())
+            .unwrap()
+    }
+
+    /// Serves QUERYs from `buckets` on one connection and records the QUERY lines.
+    fn fake_sonic(buckets: Vec<(&'static str, Vec<&'static str>)>) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&queries);
+        spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut out = socket.try_clone().unwrap();
+            for (n, line) in BufReader::new(socket).lines().enumerate() {
+                let line = line.unwrap();
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                let reply = match tokens[0] {
+                    "START" => String::from("STARTED search protocol(1) buffer(20000)\r\n"),
+                    "PING" => String::from("PONG\r\n"),
+                    "QUERY" => {
+                        seen.lock().unwrap().push(line.clone());
+                        let ids = &buckets.iter().find(|(b, _)| *b == tokens[2]).unwrap().1;
+                        let found: Vec<&str> = ids
+                            .iter()
+                            .skip(option(&tokens, "OFFSET"))
+                            .take(option(&tokens, "LIMIT"))
+                            .cloned()
+                            .collect();
+                        format!("PENDING q{0}\r\nEVENT QUERY q{0} {1}\r\n", n, found.join(" "))
+                    }
+                    _ => String::from("ERR unknown\r\n"),
+                };
+                out.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        (addr, queries)
+    }
+
+    #[test]
+    fn pages_each_bucket_with_limit_and_offset() {
+        let (addr, queries) = fake_sonic(vec![
+            ("name", vec!["all:a", "all:b", "all:c", "all:d"]),
+            ("desc", vec!["all:c", "all:e"]),
+        ]);
+        let client = Client::connect(ClientOptions {
+            addr,
+            ..ClientOptions::default()
+        })
+        .unwrap()
+        .with_store(Ids);
+        let ids = |page: Page| -> Vec<String> {
+            page.docs.into_iter().map(|(_, id)| String::from_utf8(id).unwrap()).collect()
+        };
+
+        let mut cursor = Cursor::default();
+        let first = client.query_page("all", "fire", &mut cursor, 3).unwrap();
+        assert_eq!(first.total, None);
+        assert_eq!(ids(first), vec!["all:a", "all:b", "all:c"]);
+
+        let second = client.query_page("all", "fire", &mut cursor, 3).unwrap();
+        assert_eq!(second.total, Some(5));
+        assert_eq!(ids(second), vec!["all:d", "all:e"]);
+        assert_eq!(
+            *queries.lock().unwrap(),
+            vec![
+                "QUERY all name \"fire\" LIMIT(3) OFFSET(0)",
+                "QUERY all name \"fire\" LIMIT(3) OFFSET(3)",
+                "QUERY all desc \"fire\" LIMIT(3) OFFSET(0)",
+            ]
+        );
     }
 }
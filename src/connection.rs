@@ -0,0 +1,180 @@
+use mio::net::TcpStream;
+use mio::{Interest, Registry, Token};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::time::Duration;
+
+/// Gives up on an unreachable server rather than waiting on the OS
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A non-blocking socket speaking a line based protocol. Output is buffered until the socket
+/// takes it and input is handed out a whole line at a time. Whoever owns it polls the registry
+/// it was registered with and calls `flush` and `read_lines` when the socket is ready.
+#[derive(Debug)]
+pub struct Connection {
+    socket: TcpStream,
+    /// Written but not yet taken by the socket
+    output: Vec<u8>,
+    /// Read but not yet ended by a newline
+    input: Vec<u8>,
+    /// Whether the connection has been established, as opposed to still being attempted
+    connected: bool,
+    closed: bool,
+}
+
+impl Connection {
+    /// Connects, waiting until the server answers, and registers the socket for reading and
+    /// writing under `token`.
+    pub fn connect_timeout(addr: SocketAddr, registry: &Registry, token: Token) -> io::Result<Self> {
+        let socket = std::net::TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        socket.set_nonblocking(true)?;
+        Self::register(TcpStream::from_std(socket), true, registry, token)
+    }
+
+    /// Starts connecting without waiting and registers the socket under `token`. The socket
+    /// becomes writable once the attempt is over; until then output is held and nothing is
+    /// read, and a failed attempt is returned as an error from `flush` or `read_lines`.
+    pub fn connect(addr: SocketAddr, registry: &Registry, token: Token) -> io::Result<Self> {
+        Self::register(TcpStream::connect(addr)?, false, registry, token)
+    }
+
+    fn register(
+        mut socket: TcpStream,
+        connected: bool,
+        registry: &Registry,
+        token: Token,
+    ) -> io::Result<Self> {
+        registry.register(&mut socket, token, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self {
+            socket,
+            output: Vec::new(),
+            input: Vec::new(),
+            connected,
+            closed: false,
+        })
+    }
+
+    /// Whether a connection started by `connect` has been established yet
+    fn ready(&mut self) -> io::Result<bool> {
+        if !self.connected {
+            if let Some(e) = self.socket.take_error()? {
+                return Err(e);
+            }
+            match self.socket.peer_addr() {
+                Ok(_) => self.connected = true,
+                Err(e) if e.kind() == io::ErrorKind::NotConnected => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.connected)
+    }
+
+    /// Queues a message for the next `flush`.
+    pub fn write(&mut self, msg: &str) {
+        debug!("[connection] writing: {}", msg.trim_end());
+        self.output.extend_from_slice(msg.as_bytes());
+    }
+
+    /// Writes as much queued output as the socket will take.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.ready()? {
+            return Ok(());
+        }
+        while !self.output.is_empty() {
+            match self.socket.write(&self.output) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads whatever has arrived and returns the complete lines, trimmed.
+    pub fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        if !self.ready()? {
+            return Ok(Vec::new());
+        }
+        let mut buf = [0; 4096];
+        while !self.closed {
+            match self.socket.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut lines = Vec::new();
+        while let Some(end) = self.input.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            trace!("[connection] read: {}", line);
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Whether the other end has closed the connection
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn shutdown(mut self, registry: &Registry) {
+        let _ = registry.deregister(&mut self.socket);
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mio::Poll;
+    use std::net::TcpListener;
+    use std::thread::sleep;
+    use std::time::Instant;
+
+    /// Flushes until the queued output has gone or `timeout` passes.
+    fn flush_within(connection: &mut Connection, timeout: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            connection.flush()?;
+            if connection.output.is_empty() || Instant::now() > deadline {
+                return Ok(());
+            }
+            sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn holds_output_until_connected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let poll = Poll::new().unwrap();
+        let mut connection =
+            Connection::connect(listener.local_addr().unwrap(), poll.registry(), Token(0)).unwrap();
+        connection.write("PING\r\n");
+        flush_within(&mut connection, Duration::from_secs(5)).unwrap();
+        assert!(connection.output.is_empty());
+
+        let (mut server, _) = listener.accept().unwrap();
+        let mut buf = [0; 6];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"PING\r\n");
+    }
+
+    #[test]
+    fn refused_connection_fails_on_flush() {
+        // Nothing listens on a port that was just released
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let poll = Poll::new().unwrap();
+        let mut connection = Connection::connect(addr, poll.registry(), Token(0)).unwrap();
+        connection.write("PING\r\n");
+        assert!(flush_within(&mut connection, Duration::from_secs(5)).is_err());
+    }
+}
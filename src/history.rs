@@ -0,0 +1,267 @@
+use quick_error::quick_error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Only this many of the most recent queries are kept around for recall.
+const RECALL_LIMIT: usize = 1000;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum HistoryError {
+        NoDataDir {
+            display("Error could not determine a data directory for history")
+        }
+        Io(err: io::Error) {
+            from()
+            display("Error reading or writing history: {}", err)
+        }
+        Format(err: serde_json::Error) {
+            from()
+            display("Error in history file: {}", err)
+        }
+    }
+}
+
+/// A submitted query. Entries are appended to the history file as one JSON object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the unix epoch
+    pub time: u64,
+    pub session: String,
+    pub query: String,
+    /// Name of the entry that was selected when the query was submitted
+    #[serde(default)]
+    pub selected: Option<String>,
+}
+
+impl Entry {
+    pub fn new(session: &str, query: &str, selected: Option<String>) -> Self {
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            session: String::from(session),
+            query: String::from(query),
+            selected,
+        }
+    }
+}
+
+/// Query history, stored in the user's data directory:
+///
+/// ```text
+/// ~/.local/share/fuzzy5e/history.jsonl
+/// ```
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+}
+
+impl History {
+    fn path() -> Result<PathBuf, HistoryError> {
+        match dirs::data_dir() {
+            Some(d) => Ok(d.join("fuzzy5e").join("history.jsonl")),
+            None => Err(HistoryError::NoDataDir),
+        }
+    }
+
+    /// Loads the history file. Lines that can't be parsed are skipped.
+    pub fn load() -> Result<Self, HistoryError> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let entries = fs::read_to_string(&path)?
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    warn!("skipping bad history line: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Appends to the history file and to the entries available for recall.
+    pub fn record(&mut self, entry: Entry) -> Result<(), HistoryError> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Distinct queries from oldest to newest, used for Up/Down recall. Repeats only keep their
+    /// most recent position.
+    pub fn queries(&self) -> Vec<String> {
+        let mut queries: Vec<String> = Vec::new();
+        for e in self.entries.iter().rev() {
+            if queries.len() >= RECALL_LIMIT {
+                break;
+            }
+            if !queries.contains(&e.query) {
+                queries.push(e.query.clone());
+            }
+        }
+        queries.reverse();
+        queries
+    }
+
+    /// Finds the newest of `queries[..before]` containing `term`, ignoring case. Returns the index
+    /// of the match.
+    pub fn search(queries: &[String], term: &str, before: usize) -> Option<usize> {
+        let term = term.to_lowercase();
+        (0..before.min(queries.len()))
+            .rev()
+            .find(|&i| queries[i].to_lowercase().contains(&term))
+    }
+}
+
+/// Formats seconds since the unix epoch as `YYYY-MM-DD HH:MM` UTC.
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let (hour, minute) = ((secs % 86_400) / 3600, (secs % 3600) / 60);
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+}
+
+/// Recall state for the query input: stepping through history with Up/Down and reverse searching
+/// with Ctrl-r.
+#[derive(Debug, Default)]
+pub struct Recall {
+    queries: Vec<String>,
+    /// Index into `queries` of the query being shown, None when editing a fresh query
+    pos: Option<usize>,
+    /// What was typed before recall started, restored when stepping past the newest query
+    draft: String,
+    /// Term being searched for, None when not searching
+    search: Option<String>,
+    found: Option<usize>,
+}
+
+impl Recall {
+    pub fn new(history: &History) -> Self {
+        Self {
+            queries: history.queries(),
+            ..Self::default()
+        }
+    }
+
+    /// Steps back to an older query. `current` is what is in the input right now.
+    pub fn prev(&mut self, current: &str) -> Option<String> {
+        let pos = match self.pos {
+            None if self.queries.is_empty() => return None,
+            None => {
+                self.draft = String::from(current);
+                self.queries.len() - 1
+            }
+            Some(0) => 0,
+            Some(p) => p - 1,
+        };
+        self.pos = Some(pos);
+        Some(self.queries[pos].clone())
+    }
+
+    /// Steps forward to a newer query, ending up back at the draft.
+    pub fn next(&mut self) -> Option<String> {
+        match self.pos {
+            None => None,
+            Some(p) if p + 1 < self.queries.len() => {
+                self.pos = Some(p + 1);
+                Some(self.queries[p + 1].clone())
+            }
+            Some(_) => {
+                self.pos = None;
+                Some(self.draft.clone())
+            }
+        }
+    }
+
+    /// Starts a reverse search, or moves to the next older match if one is in progress.
+    pub fn search(&mut self) {
+        match &self.search {
+            None => {
+                self.search = Some(String::default());
+                self.found = None;
+            }
+            Some(term) => {
+                let before = self.found.unwrap_or_else(|| self.queries.len());
+                if let Some(i) = History::search(&self.queries, term, before) {
+                    self.found = Some(i);
+                }
+            }
+        }
+    }
+
+    pub fn search_push(&mut self, ch: char) {
+        if let Some(term) = &mut self.search {
+            term.push(ch);
+        }
+        self.refind();
+    }
+
+    pub fn search_pop(&mut self) {
+        if let Some(term) = &mut self.search {
+            term.pop();
+        }
+        self.refind();
+    }
+
+    fn refind(&mut self) {
+        if let Some(term) = &self.search {
+            self.found = History::search(&self.queries, term, self.queries.len());
+        }
+    }
+
+    /// The search term and the query it currently matches, while searching.
+    pub fn searching(&self) -> Option<(String, String)> {
+        let term = self.search.clone()?;
+        let found = self
+            .found
+            .map(|i| self.queries[i].clone())
+            .unwrap_or_default();
+        Some((term, found))
+    }
+
+    /// Ends the search, returning the matched query if accepted.
+    pub fn finish_search(&mut self, accept: bool) -> Option<String> {
+        self.search = None;
+        let found = self.found.take().map(|i| self.queries[i].clone());
+        if accept {
+            found
+        } else {
+            None
+        }
+    }
+
+    /// Called after a query is recorded so recall starts over from the newest query.
+    pub fn reset(&mut self, history: &History) {
+        *self = Self::new(history);
+    }
+}
@@ -0,0 +1,132 @@
+use lazy_static::lazy_static;
+use quick_error::quick_error;
+use std::str::FromStr;
+use std::sync::RwLock;
+use unicode_width::UnicodeWidthStr;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum IconError {
+        UnknownIconSet(name: String) {
+            display("Error unknown icon set \"{}\", expected emoji, nerd, ascii or none", name)
+        }
+    }
+}
+
+lazy_static! {
+    static ref ICONS: RwLock<IconSet> = RwLock::new(IconSet::Emoji);
+}
+
+/// Replaces the icon set used for result names.
+pub fn set(icons: IconSet) {
+    if let Ok(mut i) = ICONS.write() {
+        *i = icons;
+    }
+}
+
+/// Prefixes a name with the icon for its kind. Icons are padded to the same display width so
+/// names line up in the results list.
+pub fn label(kind: Kind, name: &str) -> String {
+    let icons = ICONS.read().map(|i| *i).unwrap_or(IconSet::None);
+    let icon = icons.icon(kind);
+    if icon.is_empty() {
+        return String::from(name);
+    }
+    let pad = icons.width().saturating_sub(icon.width());
+    format!("{}{} {}", icon, " ".repeat(pad), name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Spell,
+    Monster,
+    Class,
+    Subclass,
+    Condition,
+    MagicSchool,
+    Equipment,
+    Feature,
+}
+
+const KINDS: [Kind; 8] = [
+    Kind::Spell,
+    Kind::Monster,
+    Kind::Class,
+    Kind::Subclass,
+    Kind::Condition,
+    Kind::MagicSchool,
+    Kind::Equipment,
+    Kind::Feature,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconSet {
+    Emoji,
+    /// Glyphs from a patched Nerd Font, https://www.nerdfonts.com
+    NerdFont,
+    /// Plain tags such as `[SPL]`, for terminals and fonts without emoji
+    Ascii,
+    None,
+}
+
+impl IconSet {
+    fn icon(self, kind: Kind) -> &'static str {
+        match self {
+            Self::Emoji => match kind {
+                Kind::Spell => "🔮",
+                Kind::Monster => "👹",
+                Kind::Class => "👤",
+                Kind::Subclass => "👤",
+                Kind::Condition => "💢",
+                Kind::MagicSchool => "📚",
+                Kind::Equipment => "🏹",
+                Kind::Feature => "💡",
+            },
+            Self::NerdFont => match kind {
+                Kind::Spell => "\u{f0d0}",
+                Kind::Monster => "\u{f188}",
+                Kind::Class => "\u{f007}",
+                Kind::Subclass => "\u{f0c0}",
+                Kind::Condition => "\u{f0e7}",
+                Kind::MagicSchool => "\u{f19d}",
+                Kind::Equipment => "\u{f132}",
+                Kind::Feature => "\u{f0eb}",
+            },
+            Self::Ascii => match kind {
+                Kind::Spell => "[SPL]",
+                Kind::Monster => "[MON]",
+                Kind::Class => "[CLS]",
+                Kind::Subclass => "[SUB]",
+                Kind::Condition => "[CND]",
+                Kind::MagicSchool => "[SCH]",
+                Kind::Equipment => "[EQP]",
+                Kind::Feature => "[FEA]",
+            },
+            Self::None => "",
+        }
+    }
+
+    /// Display width of the widest icon in the set
+    fn width(self) -> usize {
+        KINDS
+            .iter()
+            .map(|k| self.icon(*k).width())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Accepts `emoji`, `nerd`, `ascii` and `none`.
+impl FromStr for IconSet {
+    type Err = IconError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emoji" => Ok(Self::Emoji),
+            "nerd" | "nerdfont" => Ok(Self::NerdFont),
+            "ascii" => Ok(Self::Ascii),
+            "none" => Ok(Self::None),
+            _ => Err(IconError::UnknownIconSet(String::from(s))),
+        }
+    }
+}
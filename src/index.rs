@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::{fmt, fmt::{Display, Formatter}};
 
@@ -5,22 +6,74 @@ use std::{fmt, fmt::{Display, Formatter}};
 pub enum IndexError {
     // ConnectionError,
     ProcessingError,
+    /// The index server refused the request, with its reason
+    Server(String),
 }
 
 impl Display for IndexError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)?;
-        Ok(())
+        match self {
+            IndexError::ProcessingError => write!(f, "Error processing index request"),
+            IndexError::Server(reason) => write!(f, "Error from the index server: {}", reason),
+        }
     }
 }
 
 impl Error for IndexError {}
+
+/// Number of results fetched at a time
+pub const PAGE_SIZE: usize = 30;
+
+/// How far a query has been paged through. Start each query with a default cursor and pass the
+/// same one back for every page after.
+#[derive(Debug, Clone, Default)]
+pub struct Cursor {
+    /// Results returned so far
+    pub offset: usize,
+    /// For indexes that page bucket by bucket: the bucket being paged
+    pub bucket: usize,
+    /// Matches read from that bucket so far, including ones already returned from another
+    pub bucket_offset: usize,
+    /// Ids returned so far, so matches in several buckets are only returned once
+    pub seen: HashSet<String>,
+}
+
+/// One page of query results: (mtype, document bytes) pairs like `Indexer::query` returns.
+pub struct Page {
+    pub docs: Vec<(String, Vec<u8>)>,
+    /// Number of matches across all pages, when the index can tell
+    pub total: Option<usize>,
+}
+
 pub trait Indexer : Clone {
     fn index<T: Index>(&self, idx: Box<T>) -> Result<(), IndexError>;
     fn index_bulk<T: Index>(&self, idx: Vec<Box<T>>) -> Result<(), IndexError>;
     fn query_ids(&self, col: &str, query: &str) -> Result<Vec<String>, IndexError>;
     fn query(&self, col: &str, query: &str) -> Result<Vec<(String, Vec<u8>)>, IndexError>;
     fn flush_all(&self, col: &str) -> Result<(), IndexError>;
+    /// Completions for a partly typed word, most likely first.
+    fn suggest(&self, col: &str, word: &str, limit: usize) -> Result<Vec<String>, IndexError>;
+
+    /// Queries the next `limit` results after `cursor`, moving it past them. The default slices
+    /// whatever `query` returns.
+    fn query_page(
+        &self,
+        col: &str,
+        query: &str,
+        cursor: &mut Cursor,
+        limit: usize,
+    ) -> Result<Page, IndexError> {
+        let docs: Vec<_> = self.query(col, query)?.into_iter().skip(cursor.offset).take(limit).collect();
+        cursor.offset += docs.len();
+        Ok(Page { docs, total: None })
+    }
+}
+
+/// Looks up whole documents for indexes, like sonic, that only return ids.
+pub trait DocumentStore: Send + Sync {
+    /// Returns (mtype, document bytes) pairs in the order of `ids`, which are of the form
+    /// `<collection>:<id>`. Ids that can't be found are left out.
+    fn documents(&self, ids: &[String]) -> Result<Vec<(String, Vec<u8>)>, IndexError>;
 }
 
 pub trait ToBytes {
@@ -32,3 +85,38 @@ pub trait Index: ToBytes {
     fn mtype(&self) -> String;
     fn tuples(&self) -> Vec<(String, String, String, String)>;
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An index whose every request fails.
+    #[derive(Clone)]
+    pub(crate) struct FailingIndex;
+
+    impl Indexer for FailingIndex {
+        fn index<T: Index>(&self, _idx: Box<T>) -> Result<(), IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+        fn index_bulk<T: Index>(&self, _idx: Vec<Box<T>>) -> Result<(), IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+        fn query_ids(&self, _col: &str, _query: &str) -> Result<Vec<String>, IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+        fn query(&self, _col: &str, _query: &str) -> Result<Vec<(String, Vec<u8>)>, IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+        fn flush_all(&self, _col: &str) -> Result<(), IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+        fn suggest(&self, _col: &str, _word: &str, _limit: usize) -> Result<Vec<String>, IndexError> {
+            Err(IndexError::ProcessingError)
+        }
+    }
+
+    #[test]
+    fn display_describes_the_error() {
+        assert_eq!(IndexError::ProcessingError.to_string(), "Error processing index request");
+    }
+}
@@ -0,0 +1,631 @@
+use crate::mouse::{Mouse, WHEEL_LINES};
+use crate::{Action, InputMode, Layout, SplitDir};
+use quick_error::quick_error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tuikit::prelude::{Event, Key};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum KeymapError {
+        Io(err: io::Error) {
+            from()
+            display("Error reading keymap: {}", err)
+        }
+        Format(err: toml::de::Error) {
+            from()
+            display("Error in keymap file: {}", err)
+        }
+        UnknownKey(key: String) {
+            display("Error unknown key \"{}\" in keymap", key)
+        }
+        UnknownAction(action: String) {
+            display("Error unknown action \"{}\" in keymap", action)
+        }
+    }
+}
+
+/// A named set of bindings. Sections are checked from most to least specific, see
+/// `Keymap::action`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Global,
+    Querying,
+    Selected,
+    Normal,
+    /// Reverse searching query history
+    Search,
+    /// Typing text to find in the focused pane
+    Find,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Global => "global",
+                Self::Querying => "querying",
+                Self::Selected => "selected",
+                Self::Normal => "normal",
+                Self::Search => "search",
+                Self::Find => "find",
+            }
+        )
+    }
+}
+
+/// The keymap file, e.g. `~/.config/fuzzy5e/keys.toml`:
+///
+/// ```toml
+/// # Navigate results with j/k after pressing Esc
+/// vi_mode = true
+///
+/// [global]
+/// "ctrl-j" = "select-next"
+/// "ctrl-k" = "select-previous"
+///
+/// [selected]
+/// "q" = "layout querying"
+/// "ctrl-v" = "unbound"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    vi_mode: bool,
+    #[serde(default)]
+    global: BTreeMap<String, String>,
+    #[serde(default)]
+    querying: BTreeMap<String, String>,
+    #[serde(default)]
+    selected: BTreeMap<String, String>,
+    #[serde(default)]
+    normal: BTreeMap<String, String>,
+    #[serde(default)]
+    search: BTreeMap<String, String>,
+    #[serde(default)]
+    find: BTreeMap<String, String>,
+}
+
+pub struct Keymap {
+    /// When set, Esc in the query input switches to a normal mode for navigating results instead
+    /// of quitting.
+    pub vi_mode: bool,
+    sections: Vec<(Section, Vec<(Key, Action)>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bind = |bindings: &[(&str, &str)]| -> Vec<(Key, Action)> {
+            bindings
+                .iter()
+                .map(|(k, a)| (parse_key(k).unwrap(), a.parse().unwrap()))
+                .collect()
+        };
+        Self {
+            vi_mode: false,
+            sections: vec![
+                (
+                    Section::Global,
+                    bind(&[
+                        ("esc", "quit"),
+                        ("ctrl-c", "quit"),
+                        ("ctrl-d", "quit"),
+                        ("ctrl-q", "quit"),
+                        ("ctrl-n", "select-next"),
+                        ("ctrl-p", "select-previous"),
+                        ("pgdown", "scroll-down 10"),
+                        ("pgup", "scroll-up 10"),
+                        ("down", "scroll-down 1"),
+                        ("up", "scroll-up 1"),
+                        ("ctrl-b", "toggle-pin"),
+                        ("ctrl-t", "open-tab"),
+                        ("ctrl-v", "split vertical"),
+                        ("ctrl-x", "split horizontal"),
+                        ("ctrl-o", "focus-next"),
+                        ("alt-l", "next-tab"),
+                        ("alt-h", "prev-tab"),
+                        ("alt-w", "close-pane"),
+                        ("alt-b", "back"),
+                        ("alt-f", "forward"),
+                        ("f1", "help"),
+                    ]),
+                ),
+                (
+                    Section::Querying,
+                    bind(&[
+                        ("backspace", "backspace"),
+                        ("ctrl-w", "delete-word"),
+                        ("delete", "delete-forward"),
+                        ("ctrl-u", "kill-to-start"),
+                        ("ctrl-k", "kill-to-end"),
+                        ("left", "cursor-left"),
+                        ("right", "cursor-right"),
+                        ("alt-b", "word-left"),
+                        ("alt-f", "word-right"),
+                        ("home", "line-start"),
+                        ("ctrl-a", "line-start"),
+                        ("end", "line-end"),
+                        ("ctrl-e", "line-end"),
+                        ("up", "history-prev"),
+                        ("down", "history-next"),
+                        ("ctrl-r", "history-search"),
+                        ("enter", "layout selected"),
+                        ("tab", "complete"),
+                    ]),
+                ),
+                (
+                    Section::Selected,
+                    bind(&[
+                        ("/", "find"),
+                        ("q", "layout querying"),
+                        ("n", "find-next"),
+                        ("N", "find-prev"),
+                        ("enter", "follow-link"),
+                        ("tab", "next-link"),
+                        ("backtab", "prev-link"),
+                        ("backspace", "back"),
+                        ("home", "scroll-top"),
+                        ("end", "scroll-bottom"),
+                        ("?", "help"),
+                    ]),
+                ),
+                (
+                    Section::Normal,
+                    bind(&[
+                        ("j", "select-next"),
+                        ("k", "select-previous"),
+                        ("ctrl-d", "scroll-down 10"),
+                        ("ctrl-u", "scroll-up 10"),
+                        ("g", "scroll-top"),
+                        ("G", "scroll-bottom"),
+                        ("i", "insert-mode"),
+                        ("a", "insert-mode"),
+                        ("/", "insert-mode"),
+                        ("q", "quit"),
+                        ("?", "help"),
+                        ("enter", "layout selected"),
+                        ("tab", "toggle-list"),
+                    ]),
+                ),
+                (
+                    Section::Search,
+                    bind(&[
+                        ("ctrl-r", "history-search"),
+                        ("backspace", "backspace"),
+                        ("enter", "history-accept"),
+                        ("esc", "history-cancel"),
+                        ("ctrl-g", "history-cancel"),
+                        ("ctrl-c", "history-cancel"),
+                    ]),
+                ),
+                (
+                    Section::Find,
+                    bind(&[
+                        ("backspace", "backspace"),
+                        ("enter", "find-accept"),
+                        ("esc", "find-cancel"),
+                        ("ctrl-g", "find-cancel"),
+                        ("ctrl-c", "find-cancel"),
+                    ]),
+                ),
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("fuzzy5e").join("keys.toml"))
+    }
+
+    /// Loads the default bindings with any from the user's keymap file layered on top.
+    pub fn load() -> Result<Self, KeymapError> {
+        let mut keymap = Self::default();
+        if let Some(path) = Self::path() {
+            if path.exists() {
+                debug!("loading keymap from {:?}", path);
+                keymap.merge(toml::from_str(&fs::read_to_string(&path)?)?)?;
+            }
+        }
+        if keymap.vi_mode {
+            keymap.bind(Section::Querying, Key::ESC, Action::NormalMode);
+        }
+        Ok(keymap)
+    }
+
+    fn merge(&mut self, file: KeymapFile) -> Result<(), KeymapError> {
+        self.vi_mode = file.vi_mode;
+        for (section, bindings) in &[
+            (Section::Global, file.global),
+            (Section::Querying, file.querying),
+            (Section::Selected, file.selected),
+            (Section::Normal, file.normal),
+            (Section::Search, file.search),
+            (Section::Find, file.find),
+        ] {
+            for (k, a) in bindings {
+                let key = parse_key(k).ok_or_else(|| KeymapError::UnknownKey(k.clone()))?;
+                if a == "unbound" {
+                    self.unbind(*section, key);
+                } else {
+                    let action = a
+                        .parse()
+                        .map_err(|_| KeymapError::UnknownAction(a.clone()))?;
+                    self.bind(*section, key, action);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn bindings_mut(&mut self, section: Section) -> &mut Vec<(Key, Action)> {
+        &mut self
+            .sections
+            .iter_mut()
+            .find(|(s, _)| *s == section)
+            .unwrap()
+            .1
+    }
+
+    fn bind(&mut self, section: Section, key: Key, action: Action) {
+        let bindings = self.bindings_mut(section);
+        match bindings.iter_mut().find(|(k, _)| *k == key) {
+            Some(binding) => binding.1 = action,
+            None => bindings.push((key, action)),
+        }
+    }
+
+    fn unbind(&mut self, section: Section, key: Key) {
+        self.bindings_mut(section).retain(|(k, _)| *k != key);
+    }
+
+    /// Sections that apply to the given layout and mode, most specific first.
+    fn active_sections(&self, layout: &Layout, mode: InputMode) -> Vec<Section> {
+        match (layout, mode) {
+            (_, InputMode::Find) => vec![Section::Find],
+            (Layout::Selected, _) => vec![Section::Selected, Section::Global],
+            (Layout::Querying, InputMode::Normal) => {
+                vec![Section::Normal, Section::Global]
+            }
+            (Layout::Querying, InputMode::Insert) => {
+                vec![Section::Querying, Section::Global]
+            }
+            (Layout::Querying, InputMode::Search) => vec![Section::Search],
+        }
+    }
+
+    /// Bindings that apply to the given layout and mode grouped by section, most specific first.
+    /// Keys shadowed by a more specific section are left out.
+    pub fn help(&self, layout: &Layout, mode: InputMode) -> Vec<(Section, Vec<(Key, Action)>)> {
+        let mut seen: Vec<Key> = Vec::new();
+        let mut help = Vec::new();
+        for section in self.active_sections(layout, mode) {
+            let bindings: Vec<(Key, Action)> = self
+                .sections
+                .iter()
+                .find(|(s, _)| *s == section)
+                .unwrap()
+                .1
+                .iter()
+                .filter(|(k, _)| !seen.contains(k))
+                .cloned()
+                .collect();
+            seen.extend(bindings.iter().map(|(k, _)| *k));
+            help.push((section, bindings));
+        }
+        help
+    }
+
+    /// Translates a terminal event into an action. Unbound characters typed into the query input
+    /// are added to the query. Mouse events, as decoded from `ev` into `mouse`, aren't
+    /// configurable.
+    pub fn action(
+        &self,
+        ev: &Event,
+        mouse: Option<Mouse>,
+        layout: &Layout,
+        mode: InputMode,
+    ) -> Option<Action> {
+        match mouse {
+            Some(Mouse::Click(row, col)) => return Some(Action::Click(row, col)),
+            Some(Mouse::DoubleClick(row, col)) => return Some(Action::DoubleClick(row, col)),
+            Some(Mouse::WheelUp) => return Some(Action::ScrollUp(WHEEL_LINES)),
+            Some(Mouse::WheelDown) => return Some(Action::ScrollDown(WHEEL_LINES)),
+            None => {}
+        }
+        let key = match ev {
+            Event::Resize { .. } => return Some(Action::Resize),
+            Event::Key(key) => *key,
+            _ => return None,
+        };
+        for section in self.active_sections(layout, mode) {
+            let bindings = &self.sections.iter().find(|(s, _)| *s == section).unwrap().1;
+            if let Some((_, action)) = bindings.iter().find(|(k, _)| *k == key) {
+                return Some(action.clone());
+            }
+        }
+        match (key, layout, mode) {
+            (Key::Char(ch), Layout::Querying, InputMode::Insert)
+            | (Key::Char(ch), Layout::Querying, InputMode::Search)
+            | (Key::Char(ch), _, InputMode::Find) => Some(Action::AddChar(ch)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses key chords such as `ctrl-n`, `alt-l`, `pgdown`, `f1` or a single character.
+pub fn parse_key(s: &str) -> Option<Key> {
+    let lower = s.to_lowercase();
+    if let Some(ch) = single_char(s) {
+        return Some(Key::Char(ch));
+    }
+    if lower.starts_with("ctrl-") {
+        return single_char(&lower[5..]).map(Key::Ctrl);
+    }
+    if lower.starts_with("alt-") {
+        return single_char(&s[4..]).map(Key::Alt);
+    }
+    if lower.starts_with('f') {
+        if let Ok(n) = lower[1..].parse::<u8>() {
+            return Some(Key::F(n));
+        }
+    }
+    Some(match &*lower {
+        "esc" => Key::ESC,
+        "enter" => Key::Enter,
+        "tab" => Key::Tab,
+        "backtab" | "shift-tab" => Key::BackTab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pgup" => Key::PageUp,
+        "pgdown" => Key::PageDown,
+        "space" => Key::Char(' '),
+        _ => return None,
+    })
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+/// The inverse of `parse_key`, used when listing bindings.
+pub fn key_name(key: &Key) -> String {
+    match key {
+        Key::Char(' ') => String::from("space"),
+        Key::Char(ch) => ch.to_string(),
+        Key::Ctrl(ch) => format!("ctrl-{}", ch),
+        Key::Alt(ch) => format!("alt-{}", ch),
+        Key::F(n) => format!("f{}", n),
+        Key::ESC => String::from("esc"),
+        Key::Enter => String::from("enter"),
+        Key::Tab => String::from("tab"),
+        Key::BackTab => String::from("shift-tab"),
+        Key::Backspace => String::from("backspace"),
+        Key::Delete => String::from("delete"),
+        Key::Insert => String::from("insert"),
+        Key::Up => String::from("up"),
+        Key::Down => String::from("down"),
+        Key::Left => String::from("left"),
+        Key::Right => String::from("right"),
+        Key::Home => String::from("home"),
+        Key::End => String::from("end"),
+        Key::PageUp => String::from("pgup"),
+        Key::PageDown => String::from("pgdown"),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Actions are written as a name optionally followed by an argument, e.g. `select-next`,
+/// `scroll-down 10` or `split vertical`.
+impl FromStr for Action {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || KeymapError::UnknownAction(String::from(s));
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or_else(unknown)?;
+        let arg = parts.next();
+        let count = || -> Result<usize, KeymapError> {
+            arg.ok_or_else(unknown)?.parse().map_err(|_| unknown())
+        };
+        Ok(match name {
+            "quit" => Action::Quit,
+            "backspace" => Action::Backspace,
+            "delete-word" => Action::DeleteWord,
+            "delete-forward" => Action::DeleteForward,
+            "kill-to-start" => Action::KillToStart,
+            "kill-to-end" => Action::KillToEnd,
+            "cursor-left" => Action::CursorLeft,
+            "cursor-right" => Action::CursorRight,
+            "word-left" => Action::WordLeft,
+            "word-right" => Action::WordRight,
+            "line-start" => Action::LineStart,
+            "line-end" => Action::LineEnd,
+            "history-prev" => Action::HistoryPrev,
+            "history-next" => Action::HistoryNext,
+            "history-search" => Action::HistorySearch,
+            "history-accept" => Action::HistoryAccept,
+            "history-cancel" => Action::HistoryCancel,
+            "select-previous" => Action::SelectPrevious,
+            "select-next" => Action::SelectNext,
+            "layout" => match arg {
+                Some("querying") => Action::SetLayout(Layout::Querying),
+                Some("selected") => Action::SetLayout(Layout::Selected),
+                _ => return Err(unknown()),
+            },
+            "scroll-up" => Action::ScrollUp(count()?),
+            "scroll-down" => Action::ScrollDown(count()?),
+            "scroll-top" => Action::ScrollTop,
+            "scroll-bottom" => Action::ScrollBottom,
+            "toggle-pin" => Action::TogglePin,
+            "toggle-list" => Action::ToggleList,
+            "complete" => Action::Complete,
+            "open-tab" => Action::OpenTab,
+            "split" => match arg {
+                Some("vertical") => Action::OpenSplit(SplitDir::Vertical),
+                Some("horizontal") => Action::OpenSplit(SplitDir::Horizontal),
+                _ => return Err(unknown()),
+            },
+            "focus-next" => Action::FocusNext,
+            "next-tab" => Action::NextTab,
+            "prev-tab" => Action::PrevTab,
+            "close-pane" => Action::ClosePane,
+            "next-link" => Action::NextLink,
+            "prev-link" => Action::PrevLink,
+            "follow-link" => Action::FollowLink,
+            "back" => Action::Back,
+            "forward" => Action::Forward,
+            "normal-mode" => Action::NormalMode,
+            "insert-mode" => Action::InsertMode,
+            "help" => Action::Help,
+            "find" => Action::Find,
+            "find-next" => Action::FindNext,
+            "find-prev" => Action::FindPrev,
+            "find-accept" => Action::FindAccept,
+            "find-cancel" => Action::FindCancel,
+            _ => return Err(unknown()),
+        })
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Quit => write!(f, "quit"),
+            Action::Backspace => write!(f, "backspace"),
+            Action::DeleteWord => write!(f, "delete-word"),
+            Action::DeleteForward => write!(f, "delete-forward"),
+            Action::KillToStart => write!(f, "kill-to-start"),
+            Action::KillToEnd => write!(f, "kill-to-end"),
+            Action::CursorLeft => write!(f, "cursor-left"),
+            Action::CursorRight => write!(f, "cursor-right"),
+            Action::WordLeft => write!(f, "word-left"),
+            Action::WordRight => write!(f, "word-right"),
+            Action::LineStart => write!(f, "line-start"),
+            Action::LineEnd => write!(f, "line-end"),
+            Action::HistoryPrev => write!(f, "history-prev"),
+            Action::HistoryNext => write!(f, "history-next"),
+            Action::HistorySearch => write!(f, "history-search"),
+            Action::HistoryAccept => write!(f, "history-accept"),
+            Action::HistoryCancel => write!(f, "history-cancel"),
+            Action::AddChar(ch) => write!(f, "add-char {}", ch),
+            Action::Paste(text) => write!(f, "paste {}", text),
+            Action::SelectPrevious => write!(f, "select-previous"),
+            Action::SelectNext => write!(f, "select-next"),
+            Action::SetLayout(Layout::Querying) => write!(f, "layout querying"),
+            Action::SetLayout(Layout::Selected) => write!(f, "layout selected"),
+            Action::ScrollUp(n) => write!(f, "scroll-up {}", n),
+            Action::ScrollDown(n) => write!(f, "scroll-down {}", n),
+            Action::ScrollTop => write!(f, "scroll-top"),
+            Action::ScrollBottom => write!(f, "scroll-bottom"),
+            Action::TogglePin => write!(f, "toggle-pin"),
+            Action::ToggleList => write!(f, "toggle-list"),
+            Action::Complete => write!(f, "complete"),
+            Action::OpenTab => write!(f, "open-tab"),
+            Action::OpenSplit(SplitDir::Vertical) => write!(f, "split vertical"),
+            Action::OpenSplit(SplitDir::Horizontal) => write!(f, "split horizontal"),
+            Action::FocusNext => write!(f, "focus-next"),
+            Action::NextTab => write!(f, "next-tab"),
+            Action::PrevTab => write!(f, "prev-tab"),
+            Action::ClosePane => write!(f, "close-pane"),
+            Action::NextLink => write!(f, "next-link"),
+            Action::PrevLink => write!(f, "prev-link"),
+            Action::FollowLink => write!(f, "follow-link"),
+            Action::Back => write!(f, "back"),
+            Action::Forward => write!(f, "forward"),
+            Action::NormalMode => write!(f, "normal-mode"),
+            Action::InsertMode => write!(f, "insert-mode"),
+            Action::Help => write!(f, "help"),
+            Action::Find => write!(f, "find"),
+            Action::FindNext => write!(f, "find-next"),
+            Action::FindPrev => write!(f, "find-prev"),
+            Action::FindAccept => write!(f, "find-accept"),
+            Action::FindCancel => write!(f, "find-cancel"),
+            Action::Click(row, col) => write!(f, "click {} {}", row, col),
+            Action::DoubleClick(row, col) => write!(f, "double-click {} {}", row, col),
+            Action::Resize => write!(f, "resize"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(s: &str) -> Result<String, KeymapError> {
+        s.parse::<Action>().map(|a| a.to_string())
+    }
+
+    #[test]
+    fn parses_actions_with_arguments() {
+        for a in &["quit", "scroll-down 10", "split vertical", "layout selected", "find-next"] {
+            assert_eq!(action(a).unwrap(), *a);
+        }
+        assert!(action("scroll-down").is_err());
+        assert!(action("scroll-down lots").is_err());
+        assert!(action("split diagonal").is_err());
+        assert!(action("teleport").is_err());
+        assert!(action("").is_err());
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_key("q"), Some(Key::Char('q')));
+        assert_eq!(parse_key("Q"), Some(Key::Char('Q')));
+        assert_eq!(parse_key("Ctrl-N"), Some(Key::Ctrl('n')));
+        assert_eq!(parse_key("alt-B"), Some(Key::Alt('B')));
+        assert_eq!(parse_key("f5"), Some(Key::F(5)));
+        assert_eq!(parse_key("shift-tab"), Some(Key::BackTab));
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("hyper-x"), None);
+        for k in &["space", "ctrl-r", "alt-b", "f12", "esc", "pgdown"] {
+            assert_eq!(key_name(&parse_key(k).unwrap()), *k);
+        }
+    }
+
+    #[test]
+    fn merges_a_keymap_file_over_the_defaults() {
+        let mut keymap = Keymap::default();
+        let file: KeymapFile = toml::from_str(
+            r#"
+            vi_mode = true
+            [global]
+            "ctrl-q" = "quit"
+            [selected]
+            "ctrl-v" = "unbound"
+            "#,
+        )
+        .unwrap();
+        keymap.merge(file).unwrap();
+        assert!(keymap.vi_mode);
+        let ev = Event::Key(Key::Ctrl('q'));
+        let found = keymap.action(&ev, None, &Layout::Selected, InputMode::Normal);
+        assert_eq!(found.map(|a| a.to_string()), Some(String::from("quit")));
+        let selected = &keymap.sections.iter().find(|(s, _)| *s == Section::Selected).unwrap().1;
+        assert!(selected.iter().all(|(k, _)| *k != Key::Ctrl('v')));
+
+        let bad: KeymapFile = toml::from_str("[global]\n\"ctrl-q\" = \"teleport\"").unwrap();
+        assert!(matches!(Keymap::default().merge(bad), Err(KeymapError::UnknownAction(_))));
+        let bad: KeymapFile = toml::from_str("[global]\n\"hyper-x\" = \"quit\"").unwrap();
+        assert!(matches!(Keymap::default().merge(bad), Err(KeymapError::UnknownKey(_))));
+    }
+}
@@ -4,35 +4,53 @@ extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 
+mod backend;
+mod buffer;
 mod client;
+mod connection;
 mod db;
+mod history;
+mod icons;
 mod index;
+mod keymap;
 mod model;
+mod mouse;
 // mod print;
-mod worker;
+mod session;
 mod tantivy;
+mod theme;
 
 use std::fs::File;
 use simplelog;
-use crate::tantivy::*;
+use backend::{Backend, BackendOptions, Engine};
+use client::{Mode, Trigger};
 use db::DB;
+use history::{Entry, History, Recall};
+use icons::IconSet;
+use index::Index;
+use keymap::{Keymap, Section};
 use model::*;
+use mouse::{Clicks, HitMap, Mouse, Recording};
 use quick_error::quick_error;
+use session::{PinnedEntry, Session, SessionError};
 use std::error::Error;
 use std::thread;
 use structopt::StructOpt;
+use theme::{ColorMode, Style, Theme};
 use tuikit::canvas;
 use tuikit::prelude::*;
 use std::fmt;
 use std::str::FromStr;
 use std::fmt::{Display,Formatter};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use std::cell::{Cell, RefCell};
 use std::sync::{
     Arc, Mutex,
 };
 
 struct Config {
-    mongo_addr: String,
-    index_dir: String,
+    backend: BackendOptions,
+    session: String,
 }
 
 quick_error! {
@@ -47,11 +65,38 @@ quick_error! {
 #[derive(Debug, StructOpt)]
 enum CliAction {
     /// [default] REPL mode
-    Run,
+    Run {
+        /// Name of the session to resume. Sessions are saved on exit.
+        #[structopt(short, long, default_value = "default")]
+        session: String,
+    },
     /// Searches for the provided query
     Query { query: String },
     /// Clears the index and pushes all documents
     Reindex,
+    /// Lists recently submitted queries, oldest first
+    History {
+        /// Number of queries to list
+        #[structopt(short = "n", long, default_value = "20")]
+        count: usize,
+        /// Only list queries from this session
+        #[structopt(short, long)]
+        session: Option<String>,
+    },
+    /// Administers the sonic server
+    Sonic(SonicAction),
+}
+
+#[derive(Debug, StructOpt)]
+enum SonicAction {
+    /// Writes pending index changes to disk, e.g. after a reindex
+    Consolidate,
+    /// Backs the index up to a directory on the server
+    Backup { path: String },
+    /// Restores the index from a backup directory on the server
+    Restore { path: String },
+    /// Prints server statistics
+    Info,
 }
 
 #[derive(StructOpt)]
@@ -67,82 +112,402 @@ struct Cli {
 
     #[structopt(short, long, default_value = "localhost:27017", env = "MONGO_ADDR")]
     mongo_addr: String,
+
+    /// Search index to use: tantivy (the local index directory) or sonic
+    #[structopt(short, long, default_value = "tantivy", env = "FUZZY5E_BACKEND")]
+    backend: Backend,
+
+    #[structopt(long, default_value = "localhost:1491", env = "SONIC_ADDR")]
+    sonic_addr: String,
+
+    #[structopt(long, default_value = "SecretPassword", env = "SONIC_PASSWORD")]
+    sonic_password: String,
+
+    /// Ingest connections to spread reindexing across
+    #[structopt(long, default_value = "1", env = "SONIC_CONNECTIONS")]
+    sonic_connections: usize,
+
+    /// Color theme: dark, light, the name of a theme in ~/.config/fuzzy5e/themes or a path
+    #[structopt(long, default_value = "dark", env = "FUZZY5E_THEME")]
+    theme: String,
+
+    /// Colors to use: auto, none, 256 or truecolor. Auto honors NO_COLOR and COLORTERM.
+    #[structopt(long, default_value = "auto")]
+    color: ColorMode,
+
+    /// Icons shown next to results: emoji, nerd, ascii or none. Defaults to the theme's choice,
+    /// or emoji.
+    #[structopt(long, env = "FUZZY5E_ICONS")]
+    icons: Option<IconSet>,
 }
 
+#[derive(Clone, Debug)]
 enum Action {
     Quit,
     Backspace,
     DeleteWord,
+    DeleteForward,
+    KillToStart,
+    KillToEnd,
+    CursorLeft,
+    CursorRight,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    HistoryAccept,
+    HistoryCancel,
     AddChar(char),
+    Paste(String),
     SelectPrevious,
     SelectNext,
     SetLayout(Layout),
     ScrollUp(usize),
     ScrollDown(usize),
+    ScrollTop,
+    ScrollBottom,
+    TogglePin,
+    ToggleList,
+    Complete,
+    OpenTab,
+    OpenSplit(SplitDir),
+    FocusNext,
+    NextTab,
+    PrevTab,
+    ClosePane,
+    NextLink,
+    PrevLink,
+    FollowLink,
+    Back,
+    Forward,
+    NormalMode,
+    InsertMode,
+    Help,
+    Find,
+    FindNext,
+    FindPrev,
+    FindAccept,
+    FindCancel,
+    Click(usize, usize),
+    DoubleClick(usize, usize),
     Resize,
 }
 
-fn update_matches(idx: impl index::Indexer, query: &str, matches: Arc<Mutex<Vec<Box<Model>>>>) {
+/// Number of completions asked for while typing
+const SUGGEST_LIMIT: usize = 5;
+
+/// The word being typed at the end of a query, without any `+`, `-` or `field:` in front of it.
+fn completable_word(query: &str) -> Option<&str> {
+    let word = query.rsplit(char::is_whitespace).next()?;
+    let word = word.rsplit(':').next()?.trim_start_matches(|c| c == '+' || c == '-');
+    if word.is_empty() || !word.chars().all(char::is_alphanumeric) {
+        None
+    } else {
+        Some(word)
+    }
+}
+
+/// Looks up a completion for the word being typed. The query it was found for is kept with it
+/// so a stale completion is never shown.
+fn update_completion(
+    idx: impl index::Indexer,
+    query: &str,
+    completion: Arc<Mutex<Option<(String, String)>>>,
+) {
+    let suggestion = completable_word(query).and_then(|word| {
+        let typed = word.to_lowercase();
+        match idx.suggest(&Model::collection(), word, SUGGEST_LIMIT) {
+            Ok(words) => words
+                .into_iter()
+                .find(|w| w.chars().count() > typed.chars().count() && w.to_lowercase().starts_with(&typed)),
+            Err(e) => {
+                warn!("failed to get completions for {}: {}", word, e);
+                None
+            }
+        }
+    });
+    if let Ok(mut completion) = completion.lock() {
+        *completion = suggestion.map(|s| (String::from(query), s));
+    }
+}
+
+fn update_matches(
+    idx: impl index::Indexer,
+    query: &str,
+    matches: Arc<Mutex<Vec<Box<Model>>>>,
+    paging: Arc<Mutex<Paging>>,
+) {
+    let mut cursor = index::Cursor::default();
+    let (page, count) = match Model::query_page(idx, query, &mut cursor) {
+        Ok(res) => res,
+        Err(e) => {
+            error!("failed to update results: {}", e);
+            return;
+        }
+    };
     if let Ok(mut matches) = matches.lock() {
         matches.clear();
-        matches.extend(Model::indexed_query(idx, query).unwrap());
+        matches.extend(page);
+        *paging.lock().unwrap() = Paging { cursor, total: count };
     }
 }
 
-struct Selection(Option<Box<Model>>, usize);
+/// The entry shown in a detail pane
+struct Selection<'a> {
+    model: Option<Box<Model>>,
+    view: View,
+    /// Index of the pane in its tab
+    pane: usize,
+    /// Updated with the size of the entry each time it is drawn
+    extent: &'a Cell<Extent>,
+    find: Option<&'a Find>,
+}
 
-impl Draw for Selection {
+impl<'a> Draw for Selection<'a> {
     fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
-        if let Some(m) = self.0.clone() {
-            m.draw(canvas, self.1.clone())
-        } else {
-            Ok(())
+        let (width, height) = canvas.size()?;
+        let content = match &self.model {
+            Some(m) => mouse::tagged(|t| t.pane = Some(self.pane), || m.draw(canvas, &self.view))?,
+            None => 0,
+        };
+        self.extent.set(Extent { content, viewport: height, width });
+
+        // Highlight find matches over the top of the entry
+        if let Some(find) = self.find {
+            let rows = self.view.scroll..self.view.scroll + height;
+            for (i, (row, col, text)) in find.matches.iter().enumerate() {
+                if rows.contains(row) {
+                    let attr = if find.current == Some(i) {
+                        theme::attr(Style::FindCurrent)
+                    } else {
+                        theme::attr(Style::FindMatch)
+                    };
+                    let _ = canvas.print_with_attr(row - self.view.scroll, *col, text, attr);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Widget for Selection<'a> {}
+
+/// Height of an entry and of the pane showing it, as of the last draw.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Extent {
+    content: usize,
+    viewport: usize,
+    width: usize,
+}
+
+/// Text being searched for in a pane and where it was found.
+#[derive(Clone, Debug, Default)]
+struct Find {
+    term: String,
+    /// Row, screen column and text of each match in the entry's lines
+    matches: Vec<(usize, usize, String)>,
+    /// The match jumped to last
+    current: Option<usize>,
+}
+
+impl Find {
+    /// Finds the term, ignoring case, in the lines of an entry.
+    fn search(&mut self, lines: &[String]) {
+        self.matches.clear();
+        self.current = None;
+        if self.term.is_empty() {
+            return;
+        }
+        let term: Vec<char> = self.term.to_lowercase().chars().collect();
+        for (row, line) in lines.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            let lower: Vec<char> = line.to_lowercase().chars().collect();
+            // Lowercasing can change the length of some characters, skip those lines
+            if lower.len() != chars.len() || chars.len() < term.len() {
+                continue;
+            }
+            let mut col = 0;
+            while col + term.len() <= chars.len() {
+                if lower[col..col + term.len()] == term[..] {
+                    let text = chars[col..col + term.len()].iter().collect();
+                    // Wide characters before the match push it further right on screen
+                    let x = chars[..col].iter().map(|c| c.width().unwrap_or(0)).sum();
+                    self.matches.push((row, x, text));
+                    col += term.len();
+                } else {
+                    col += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The find prompt shown under the panes while finding.
+struct FindBar {
+    find: Option<Find>,
+    typing: bool,
+}
+
+impl Draw for FindBar {
+    fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
+        if let Some(find) = &self.find {
+            let prompt = format!("/{}", find.term);
+            let status = match (find.current, find.matches.len()) {
+                (_, 0) if !find.term.is_empty() => String::from("no matches"),
+                (Some(i), n) => format!("{}/{}", i + 1, n),
+                _ => String::default(),
+            };
+            let _ = canvas.print_with_attr(0, 0, &prompt, theme::attr(Style::Prompt));
+            let _ = canvas.print_with_attr(0, prompt.width() + 2, &status, Attr::default());
+            if self.typing {
+                let _ = canvas.set_cursor(0, prompt.width());
+            }
         }
+        Ok(())
     }
 }
 
-impl Widget for Selection {}
+impl Widget for FindBar {}
 
-struct Matches {
+impl Extent {
+    /// The furthest a pane can usefully be scrolled
+    fn max_scroll(&self) -> usize {
+        self.content.saturating_sub(self.viewport)
+    }
+
+    /// Where the pane is scrolled to, as shown in its border: Top, Bot or a percentage. Nothing
+    /// when the whole entry fits.
+    fn position(&self, scroll: usize) -> Option<String> {
+        let max = self.max_scroll();
+        if max == 0 {
+            None
+        } else if scroll == 0 {
+            Some(String::from("Top"))
+        } else if scroll >= max {
+            Some(String::from("Bot"))
+        } else {
+            Some(format!("{}%", scroll * 100 / max))
+        }
+    }
+}
+
+struct Matches<'a> {
     matches: Arc<Mutex<Vec<Box<Model>>>>,
     selected: usize,
+    /// Ids of pinned entries so they can be marked in the list
+    pinned: Vec<String>,
+    /// First row in view. Kept between draws so the list only scrolls when the selection leaves
+    /// the window.
+    top: &'a Cell<usize>,
 }
 
-impl Draw for Matches {
+impl<'a> Draw for Matches<'a> {
     fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
-        let selected_attr = Attr {
-            bg: Color::WHITE,
-            fg: Color::BLACK,
-            ..Attr::default()
-        };
+        let (width, height) = canvas.size()?;
+        let selected_attr = theme::attr(Style::SelectedRow);
+
+        let mut top = self.top.get();
+        if self.selected < top {
+            top = self.selected;
+        } else if height > 0 && self.selected >= top + height {
+            top = self.selected + 1 - height;
+        }
+        self.top.set(top);
+
         if let Ok(matches) = self.matches.lock() {
-            for (idx, result) in matches.iter().enumerate() {
+            for (row, (idx, result)) in matches.iter().enumerate().skip(top).take(height).enumerate() {
                 let (text, text_attr) = result.display_name();
+                let marker = if self.pinned.contains(&result.id()) { '*' } else { ' ' };
                 let (fmt_text, attr) = if self.selected == idx {
-                    (format!("> {}", text), selected_attr)
+                    (format!(">{}{}", marker, text), selected_attr)
                 } else {
-                    (format!("  {}", text), text_attr)
+                    (format!(" {}{}", marker, text), text_attr)
                 };
-                let _ = canvas.print_with_attr(idx, 0, &fmt_text, attr);
+                // Padded out to the full width so the whole row can be clicked
+                let text = truncate(&fmt_text, width);
+                let text = format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())));
+                let _ = mouse::tagged(
+                    |t| t.result = Some(idx),
+                    || canvas.print_with_attr(row, 0, &text, attr),
+                );
             }
         }
         Ok(())
     }
 }
 
-impl Widget for Matches {}
+impl<'a> Widget for Matches<'a> {}
+
+/// Cuts text down to fit in `width` columns, ending with an ellipsis when anything was cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return String::from(text);
+    }
+    let mut s = String::default();
+    let mut w = 0;
+    for ch in text.chars() {
+        w += ch.width().unwrap_or(0);
+        if w + 1 > width {
+            break;
+        }
+        s.push(ch);
+    }
+    s.push('…');
+    s
+}
 
-struct Input(Arc<Mutex<Query>>);
+/// The query input. Shows the reverse search prompt while searching history, and the rest of a
+/// suggested completion after the cursor.
+struct Input(Arc<Mutex<Query>>, Option<(String, String)>, Option<String>);
 impl Draw for Input {
     fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
-        let placeholder = ("? Begin typing...", Attr::from(Color::LIGHT_BLUE));
+        let placeholder = ("? Begin typing...", theme::attr(Style::Prompt));
+        let prompt = "? ";
+        if let Some((term, found)) = &self.1 {
+            let prefix = format!("(reverse-i-search)`{}': ", term);
+            let _ = canvas.print_with_attr(0, 0, &prefix, theme::attr(Style::Prompt));
+            let _ = canvas.print_with_attr(0, prefix.width(), found, Attr::default());
+            let _ = canvas.set_cursor(0, prefix.width() - 3);
+            return Ok(());
+        }
         if let Ok(s) = self.0.lock() {
             if s.is_empty() {
                 let _ = canvas.print_with_attr(0, 0, placeholder.0, placeholder.1);
-            } else {
-                let _ = canvas.print_with_attr(0, 0, &format!("? {}", s), Attr::default());
+                let _ = canvas.set_cursor(0, prompt.len());
+                return Ok(());
+            }
+            let (width, _height) = canvas.size()?;
+            let avail = width.saturating_sub(prompt.len() + 1);
+            let char_width = |ch: char| ch.width().unwrap_or(0);
+
+            // Scroll horizontally so that the cursor stays in view
+            let before: Vec<char> = s.inner.chars().take(s.cursor).collect();
+            let mut cursor_col: usize = before.iter().map(|ch| char_width(*ch)).sum();
+            let mut start = 0;
+            while cursor_col > avail && start < before.len() {
+                cursor_col -= char_width(before[start]);
+                start += 1;
+            }
+
+            let mut text = String::default();
+            let mut text_width = 0;
+            for ch in s.inner.chars().skip(start) {
+                text_width += char_width(ch);
+                if text_width > avail {
+                    break;
+                }
+                text.push(ch);
+            }
+            let _ = canvas.print_with_attr(0, 0, prompt, Attr::default());
+            let _ = canvas.print_with_attr(0, prompt.len(), &text, Attr::default());
+            if let Some(ghost) = &self.2 {
+                let col = prompt.len() + cursor_col;
+                let _ = canvas.print_with_attr(0, col, ghost, theme::attr(Style::Completion));
             }
-            let _ = canvas.set_cursor(0, 2 + s.to_string().len());
+            let _ = canvas.set_cursor(0, prompt.len() + cursor_col);
         }
         Ok(())
     }
@@ -150,30 +515,8 @@ impl Draw for Input {
 
 impl Widget for Input {}
 
-fn key_to_action(ev: Event) -> Option<Action> {
-    match ev {
-        Event::Resize{..} => Some(Action::Resize),
-        Event::Key(Key::ESC)
-        | Event::Key(Key::Ctrl('c'))
-        | Event::Key(Key::Ctrl('d'))
-        | Event::Key(Key::Ctrl('q')) => Some(Action::Quit),
-        Event::Key(Key::Backspace) => Some(Action::Backspace),
-        Event::Key(Key::Char('/')) => Some(Action::SetLayout(Layout::Querying)),
-        Event::Key(Key::Char(key)) => Some(Action::AddChar(key)),
-        Event::Key(Key::Ctrl('w')) => Some(Action::DeleteWord),
-        Event::Key(Key::Ctrl('n')) => Some(Action::SelectNext),
-        Event::Key(Key::Ctrl('p')) => Some(Action::SelectPrevious),
-        Event::Key(Key::Enter) => Some(Action::SetLayout(Layout::Selected)),
-        Event::Key(Key::PageDown) => Some(Action::ScrollDown(10)),
-        Event::Key(Key::PageUp) => Some(Action::ScrollUp(10)),
-        Event::Key(Key::Down) => Some(Action::ScrollDown(1)),
-        Event::Key(Key::Up) => Some(Action::ScrollUp(1)),
-        _ => None,
-    }
-}
-
 /// Layout determines the presentation of the screen.
-#[derive(PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 enum Layout {
     /// Querying means that the search input pane will be shown as well as the matches
     Querying,
@@ -181,18 +524,347 @@ enum Layout {
     Selected,
 }
 
+/// Whether typing goes into the query. Normal mode is only available with `vi_mode` set in the
+/// keymap and lets the results be navigated with plain keys like j/k.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InputMode {
+    Insert,
+    Normal,
+    /// Reverse searching query history
+    Search,
+    /// Typing text to find in the focused pane
+    Find,
+}
+
+/// Which list is shown in the left hand pane.
+#[derive(PartialEq)]
+enum List {
+    /// Matches for the current query
+    Results,
+    /// Entries the user pinned; these don't change as the query changes
+    Pinned,
+}
+
+/// Direction in which the panes of a tab are laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SplitDir {
+    /// Panes side by side
+    Vertical,
+    /// Panes stacked on top of each other
+    Horizontal,
+}
+
+/// A detail pane. Each pane keeps the model it is showing and its own scroll offset; only the
+/// focused pane follows the selection in the results list.
+#[derive(Clone, Default)]
+struct Pane {
+    model: Option<Box<Model>>,
+    scroll: usize,
+    /// Highlighted cross-reference, an index into `Model::references`
+    link: Option<usize>,
+    /// Entries visited by following links, with the scroll they were left at
+    back: Vec<(Box<Model>, usize)>,
+    forward: Vec<(Box<Model>, usize)>,
+    extent: Cell<Extent>,
+    find: Option<Find>,
+}
+
+impl Pane {
+    fn view(&self) -> View {
+        View {
+            scroll: self.scroll,
+            link: self.link,
+        }
+    }
+
+    fn show(&mut self, model: Option<Box<Model>>, scroll: usize) {
+        self.model = model;
+        self.scroll = scroll;
+        self.link = None;
+        self.find = None;
+    }
+
+    /// Searches the entry for the find term again, jumping to the first match at or below the
+    /// top of the pane.
+    fn refind(&mut self) {
+        let lines = match &self.model {
+            Some(m) => m.lines(self.extent.get().width),
+            None => Vec::new(),
+        };
+        let scroll = self.scroll;
+        if let Some(find) = &mut self.find {
+            find.search(&lines);
+            if !find.matches.is_empty() {
+                let next = find.matches.iter().position(|(row, _, _)| *row >= scroll);
+                find.current = Some(next.unwrap_or(0));
+            }
+        }
+        self.scroll_to_match();
+    }
+
+    /// Moves to the next or previous find match, wrapping around.
+    fn step_find(&mut self, forward: bool) {
+        if let Some(find) = &mut self.find {
+            let count = find.matches.len();
+            if count == 0 {
+                return;
+            }
+            find.current = Some(match (find.current, forward) {
+                (None, _) => 0,
+                (Some(i), true) => (i + 1) % count,
+                (Some(i), false) => (i + count - 1) % count,
+            });
+        }
+        self.scroll_to_match();
+    }
+
+    /// Scrolls the current match into view, a few rows from the top.
+    fn scroll_to_match(&mut self) {
+        let row = self
+            .find
+            .as_ref()
+            .and_then(|f| f.current.map(|i| f.matches[i].0));
+        if let Some(row) = row {
+            let extent = self.extent.get();
+            if row < self.scroll || row >= self.scroll + extent.viewport {
+                self.scroll = row.saturating_sub(2).min(extent.max_scroll());
+            }
+        }
+    }
+
+    /// Moves the link highlight forward or back, wrapping around the entry's references.
+    fn cycle_link(&mut self, forward: bool) {
+        let count = self.model.as_ref().map_or(0, |m| m.references().len());
+        self.link = if count == 0 {
+            None
+        } else {
+            Some(match (self.link, forward) {
+                (None, true) => 0,
+                (None, false) => count - 1,
+                (Some(l), true) => (l + 1) % count,
+                (Some(l), false) => (l + count - 1) % count,
+            })
+        };
+    }
+
+    fn link(&self) -> Option<Reference> {
+        let m = self.model.as_ref()?;
+        m.references().get(self.link?).cloned()
+    }
+
+    /// Shows `model`, remembering the current entry so it can be returned to.
+    fn visit(&mut self, model: Box<Model>) {
+        if let Some(m) = self.model.take() {
+            self.back.push((m, self.scroll));
+        }
+        self.forward.clear();
+        self.show(Some(model), 0);
+    }
+
+    fn back(&mut self) {
+        if let Some((m, scroll)) = self.back.pop() {
+            if let Some(cur) = self.model.take() {
+                self.forward.push((cur, self.scroll));
+            }
+            self.show(Some(m), scroll);
+        }
+    }
+
+    fn forward(&mut self) {
+        if let Some((m, scroll)) = self.forward.pop() {
+            if let Some(cur) = self.model.take() {
+                self.back.push((cur, self.scroll));
+            }
+            self.show(Some(m), scroll);
+        }
+    }
+}
+
+/// A tab holds one or more panes laid out in a single direction. Splitting a tab in the other
+/// direction re-orients all of its panes.
+struct Tab {
+    panes: Vec<Pane>,
+    split: SplitDir,
+    focus: usize,
+}
+
+impl Tab {
+    fn new(pane: Pane) -> Self {
+        Tab {
+            panes: vec![pane],
+            split: SplitDir::Vertical,
+            focus: 0,
+        }
+    }
+
+    fn title(&self) -> String {
+        match &self.panes[self.focus].model {
+            Some(m) => m.display_name().0,
+            None => String::from("(empty)"),
+        }
+    }
+}
+
+/// Query syntax understood by the tantivy index, shown in the help overlay
+const QUERY_SYNTAX: &[(&str, &str)] = &[
+    ("fire bolt", "entries matching any of the words"),
+    ("\"fire bolt\"", "the exact phrase"),
+    ("+fire -bolt", "must / must not contain a word"),
+    ("fire AND bolt", "both words; OR for either"),
+    ("name:dragon", "search only names"),
+    ("desc:poison", "search only descriptions"),
+];
+
+/// Query syntax the backend understands. Sonic matches plain words only.
+fn query_syntax(backend: Backend) -> &'static [(&'static str, &'static str)] {
+    match backend {
+        Backend::Tantivy => QUERY_SYNTAX,
+        Backend::Sonic => &[],
+    }
+}
+
+/// What the help overlay lists
+struct Help {
+    bindings: Vec<(Section, Vec<(Key, Action)>)>,
+    syntax: &'static [(&'static str, &'static str)],
+    /// First line in view. Kept within the lines there are each time the overlay is drawn.
+    scroll: Cell<usize>,
+}
+
+/// A box drawn over the middle of the screen listing the active key bindings and query syntax.
+struct HelpOverlay<'a>(&'a Help);
+
+impl<'a> Draw for HelpOverlay<'a> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
+        let mut lines: Vec<(String, Attr)> = Vec::new();
+        for (section, bindings) in &self.0.bindings {
+            if bindings.is_empty() {
+                continue;
+            }
+            lines.push((section.to_string(), theme::attr(Style::Heading)));
+            for (key, action) in bindings {
+                lines.push((format!("  {:<12} {}", keymap::key_name(key), action), Attr::default()));
+            }
+            lines.push((String::default(), Attr::default()));
+        }
+        if !self.0.syntax.is_empty() {
+            lines.push((String::from("query syntax"), theme::attr(Style::Heading)));
+            for (example, meaning) in self.0.syntax {
+                lines.push((format!("  {:<16} {}", example, meaning), Attr::default()));
+            }
+        }
+
+        let (width, height) = canvas.size()?;
+        let box_width = width.saturating_sub(4).min(64);
+        let box_height = height.saturating_sub(2).min(lines.len() + 2);
+        if box_width < 3 || box_height < 3 {
+            return Ok(());
+        }
+        let visible = box_height - 2;
+        let scroll = self.0.scroll.get().min(lines.len() - visible);
+        self.0.scroll.set(scroll);
+        let top = (height - box_height) / 2;
+        let left = (width - box_width) / 2;
+        let inner = box_width - 2;
+
+        let border = theme::attr(Style::PaneTitle);
+        let title = " Help, press any key to close ";
+        let _ = canvas.print_with_attr(top, left, &format!("┌{}┐", "─".repeat(inner)), border);
+        let _ = canvas.print_with_attr(top, left + 2, &truncate(title, inner - 2), border);
+        for row in 1..box_height - 1 {
+            let (text, attr) = lines.get(scroll + row - 1).cloned().unwrap_or_default();
+            let text = truncate(&text, inner - 1);
+            let _ = canvas.print_with_attr(top + row, left, "│", border);
+            let _ = canvas.print_with_attr(top + row, left + 1, &" ".repeat(inner), Attr::default());
+            let _ = canvas.print_with_attr(top + row, left + 2, &text, attr);
+            let _ = canvas.print_with_attr(top + row, left + box_width - 1, "│", border);
+        }
+        let _ = canvas.print_with_attr(
+            top + box_height - 1,
+            left,
+            &format!("└{}┘", "─".repeat(inner)),
+            border,
+        );
+        if visible < lines.len() {
+            let more = format!(
+                " {}-{} of {}, ↑/↓ to scroll ",
+                scroll + 1,
+                scroll + visible,
+                lines.len()
+            );
+            let _ = canvas.print_with_attr(
+                top + box_height - 1,
+                left + 2,
+                &truncate(&more, inner - 2),
+                border,
+            );
+        }
+        Ok(())
+    }
+}
+
+struct TabBar {
+    titles: Vec<String>,
+    current: usize,
+}
+
+impl Draw for TabBar {
+    fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
+        let mut col = 0;
+        for (idx, title) in self.titles.iter().enumerate() {
+            let text = format!(" {}:{} ", idx + 1, title);
+            let attr = if idx == self.current {
+                theme::attr(Style::ActiveTab)
+            } else {
+                Attr::default()
+            };
+            col += canvas.print_with_attr(0, col, &text, attr)?;
+        }
+        Ok(())
+    }
+}
+
+impl Widget for TabBar {}
+
+/// How far the results of the current query have been loaded
+#[derive(Default)]
+struct Paging {
+    cursor: index::Cursor,
+    /// Number of matches for the query, which can be more than have been loaded
+    total: Option<usize>,
+}
+
 struct Screen5e {
     query: Arc<Mutex<Query>>,
     matches: Arc<Mutex<Vec<Box<Model>>>>,
+    paging: Arc<Mutex<Paging>>,
+    /// Completion for the last word of a query, and the query it was found for
+    completion: Arc<Mutex<Option<(String, String)>>>,
     selected: usize,
-    scroll: usize,
+    pinned: Arc<Mutex<Vec<Box<Model>>>>,
+    pin_selected: usize,
+    list: List,
+    /// First row in view in the list pane
+    list_top: Cell<usize>,
+    tabs: Vec<Tab>,
+    tab: usize,
     term: Arc<Term>,
     layout: Layout,
+    mode: InputMode,
+    session: String,
+    history: History,
+    recall: Recall,
+    /// What the help overlay lists while it is open
+    help: Option<Help>,
+    /// What was drawn where, for mouse clicks
+    hits: RefCell<HitMap>,
 }
 
 enum Scroll {
     Up(usize),
     Down(usize),
+    Top,
+    Bottom,
 }
 
 /* This doesn't work, so added the "cleanup" method to the impl instead
@@ -204,14 +876,202 @@ impl Drop for Screen5e {
 */
 
 impl Screen5e {
-    fn new(term: Arc<Term>, query: Arc<Mutex<Query>>, matches: Arc<Mutex<Vec<Box<Model>>>>) -> Screen5e {
+    fn new(
+        term: Arc<Term>,
+        query: Arc<Mutex<Query>>,
+        matches: Arc<Mutex<Vec<Box<Model>>>>,
+        paging: Arc<Mutex<Paging>>,
+        completion: Arc<Mutex<Option<(String, String)>>>,
+        pinned: Arc<Mutex<Vec<Box<Model>>>>,
+        session: String,
+        history: History,
+    ) -> Screen5e {
+        let recall = Recall::new(&history);
         Screen5e {
             query,
             matches,
+            paging,
+            completion,
             selected: 0,
-            scroll: 0,
+            pinned,
+            pin_selected: 0,
+            list: List::Results,
+            list_top: Cell::new(0),
+            tabs: vec![Tab::new(Pane::default())],
+            tab: 0,
             term,
             layout: Layout::Querying,
+            mode: InputMode::Insert,
+            session,
+            history,
+            recall,
+            help: None,
+            hits: RefCell::new(HitMap::default()),
+        }
+    }
+
+    /// Selects a clicked result, opening it on a double click, or focuses a clicked pane.
+    /// Returns the cross-reference that was clicked, if any, so it can be looked up and followed.
+    fn click(&mut self, row: usize, col: usize, double: bool) -> Option<Reference> {
+        let target = self.hits.borrow().get(row, col);
+        if let Some(i) = target.result {
+            *self.active_selected_mut() = i;
+            self.follow_selection();
+            if double && self.layout == Layout::Querying {
+                self.record_query();
+                self.set_layout(Layout::Selected);
+            } else {
+                self.update();
+            }
+            return None;
+        }
+        if let Some(p) = target.pane {
+            self.tabs[self.tab].focus = p;
+            self.focused_mut().link = target.link;
+            self.update();
+            return target.link.and_then(|_| self.focused_link());
+        }
+        None
+    }
+
+    /// The rest of the suggested completion for the word being typed, while the cursor is at the
+    /// end of the query.
+    fn ghost(&self) -> Option<String> {
+        let query = self.query.lock().ok()?;
+        if query.cursor != query.len() || self.recall.searching().is_some() {
+            return None;
+        }
+        let completion = self.completion.lock().ok()?;
+        let (for_query, word) = completion.as_ref()?;
+        if *for_query != query.inner {
+            return None;
+        }
+        let typed = completable_word(&query.inner)?.chars().count();
+        Some(word.chars().skip(typed).collect())
+    }
+
+    /// Accepts the suggested completion. Returns false if there wasn't one.
+    fn complete(&mut self) -> bool {
+        let ghost = match self.ghost() {
+            Some(ghost) => ghost,
+            None => return false,
+        };
+        if let Ok(mut query) = self.query.lock() {
+            ghost.chars().for_each(|ch| query.push(ch));
+        }
+        self.update();
+        true
+    }
+
+    fn show_help(&mut self, keymap: &Keymap, backend: Backend) {
+        self.help = Some(Help {
+            bindings: keymap.help(&self.layout, self.mode),
+            syntax: query_syntax(backend),
+            scroll: Cell::new(0),
+        });
+        self.update();
+    }
+
+    fn scroll_help(&mut self, scroll: Scroll) {
+        if let Some(help) = &self.help {
+            let s = help.scroll.get();
+            help.scroll.set(match scroll {
+                Scroll::Up(n) => s.saturating_sub(n),
+                // Drawing brings this back within the lines there are
+                Scroll::Down(n) => s + n,
+                Scroll::Top => 0,
+                Scroll::Bottom => usize::MAX,
+            });
+        }
+        self.update();
+    }
+
+    fn close_help(&mut self) {
+        self.help = None;
+        self.update();
+    }
+
+    /// Records the current query and selection in the history.
+    fn record_query(&mut self) {
+        let query = self.query.lock().unwrap().to_string();
+        if query.is_empty() {
+            return;
+        }
+        let selected = self.selection().map(|m| m.name());
+        if let Err(e) = self.history.record(Entry::new(&self.session, &query, selected)) {
+            error!("failed to record history: {}", e);
+        }
+        self.recall.reset(&self.history);
+    }
+
+    fn set_query(&mut self, query: &str) {
+        self.query.lock().unwrap().set(query);
+        self.set_selected(0);
+    }
+
+    fn history_prev(&mut self) {
+        let current = self.query.lock().unwrap().to_string();
+        if let Some(q) = self.recall.prev(&current) {
+            self.set_query(&q);
+        }
+    }
+
+    fn history_next(&mut self) {
+        if let Some(q) = self.recall.next() {
+            self.set_query(&q);
+        }
+    }
+
+    /// Starts a reverse history search, or finds the next older match if already searching.
+    fn history_search(&mut self) {
+        self.recall.search();
+        self.set_mode(InputMode::Search);
+    }
+
+    /// Starts finding text in the focused pane.
+    fn start_find(&mut self) {
+        self.focused_mut().find = Some(Find::default());
+        self.set_mode(InputMode::Find);
+    }
+
+    fn find_push(&mut self, ch: char) {
+        let pane = self.focused_mut();
+        if let Some(find) = &mut pane.find {
+            find.term.push(ch);
+        }
+        pane.refind();
+        self.update();
+    }
+
+    fn find_pop(&mut self) {
+        let pane = self.focused_mut();
+        if let Some(find) = &mut pane.find {
+            find.term.pop();
+        }
+        pane.refind();
+        self.update();
+    }
+
+    /// Stops typing the find term. Matches stay highlighted for n/N unless cancelled.
+    fn finish_find(&mut self, accept: bool) {
+        let pane = self.focused_mut();
+        let keep = accept && pane.find.as_ref().map_or(false, |f| !f.term.is_empty());
+        if !keep {
+            pane.find = None;
+        }
+        self.set_mode(InputMode::Insert);
+    }
+
+    fn step_find(&mut self, forward: bool) {
+        self.focused_mut().step_find(forward);
+        self.update();
+    }
+
+    fn finish_search(&mut self, accept: bool) {
+        self.mode = InputMode::Insert;
+        match self.recall.finish_search(accept) {
+            Some(q) => self.set_query(&q),
+            None => self.update(),
         }
     }
 
@@ -221,67 +1081,372 @@ impl Screen5e {
 
     fn set_selected(&mut self, selected: usize) {
         self.selected = selected;
+        self.list = List::Results;
+        self.follow_selection();
+        self.update();
+    }
+
+    /// The list currently shown in the left pane along with its selected index
+    fn active_list(&self) -> (&Arc<Mutex<Vec<Box<Model>>>>, usize) {
+        match self.list {
+            List::Results => (&self.matches, self.selected),
+            List::Pinned => (&self.pinned, self.pin_selected),
+        }
+    }
+
+    fn active_selected_mut(&mut self) -> &mut usize {
+        match self.list {
+            List::Results => &mut self.selected,
+            List::Pinned => &mut self.pin_selected,
+        }
+    }
+
+    fn selection(&self) -> Option<Box<Model>> {
+        let (list, selected) = self.active_list();
+        if let Ok(list) = list.lock() {
+            list.get(selected).cloned()
+        } else {
+            None
+        }
+    }
+
+    fn focused(&self) -> &Pane {
+        let tab = &self.tabs[self.tab];
+        &tab.panes[tab.focus]
+    }
+
+    fn focused_mut(&mut self) -> &mut Pane {
+        let tab = &mut self.tabs[self.tab];
+        &mut tab.panes[tab.focus]
+    }
+
+    /// Points the focused pane at the current selection. Scroll is only reset when the selection
+    /// actually changed so that refreshing the matches doesn't lose your place.
+    fn follow_selection(&mut self) {
+        let sel = self.selection();
+        let pane = self.focused_mut();
+        let changed = match (&pane.model, &sel) {
+            (Some(a), Some(b)) => a.id() != b.id(),
+            (None, None) => false,
+            _ => true,
+        };
+        if changed {
+            pane.show(sel, 0);
+            pane.back.clear();
+            pane.forward.clear();
+        }
+    }
+
+    fn toggle_list(&mut self) {
+        self.list = match self.list {
+            List::Results => List::Pinned,
+            List::Pinned => List::Results,
+        };
+        self.follow_selection();
         self.update();
     }
 
+    /// Pins the current selection, or unpins it if it was already pinned.
+    fn toggle_pin(&mut self) {
+        if let Some(m) = self.selection() {
+            let id = m.id();
+            if let Ok(mut pinned) = self.pinned.lock() {
+                if let Some(pos) = pinned.iter().position(|p| p.id() == id) {
+                    pinned.remove(pos);
+                    if self.pin_selected >= pinned.len() && self.pin_selected > 0 {
+                        self.pin_selected -= 1;
+                    }
+                } else {
+                    pinned.push(m);
+                }
+            }
+            self.follow_selection();
+            self.update();
+        }
+    }
+
     fn set_layout(&mut self, layout: Layout) {
         self.layout = layout;
         self.update();
     }
 
+    fn set_mode(&mut self, mode: InputMode) {
+        self.mode = mode;
+        self.update();
+    }
+
+    /// Whether the last loaded result is selected and the index may have more.
+    fn at_end_of_results(&self) -> bool {
+        if self.list != List::Results {
+            return false;
+        }
+        let loaded = self.matches.lock().map(|m| m.len()).unwrap_or(0);
+        let more = match self.paging.lock().unwrap().total {
+            Some(total) => loaded < total,
+            // Without a count, a full last page suggests there could be more
+            None => loaded > 0 && loaded % index::PAGE_SIZE == 0,
+        };
+        more && self.selected + 1 >= loaded
+    }
+
+    /// Fetches the next page of results for the current query and appends it.
+    fn load_more(&mut self, idx: impl index::Indexer) {
+        let query = self.query.lock().unwrap().to_string();
+        let mut cursor = self.paging.lock().unwrap().cursor.clone();
+        let offset = cursor.offset;
+        match Model::query_page(idx, &query, &mut cursor) {
+            Ok((page, count)) => {
+                let mut matches = self.matches.lock().unwrap();
+                let mut paging = self.paging.lock().unwrap();
+                // The update thread may have replaced the results for a newer query meanwhile
+                if paging.cursor.offset != offset || self.query.lock().unwrap().to_string() != query
+                {
+                    return;
+                }
+                paging.total = if page.is_empty() { Some(matches.len()) } else { count };
+                paging.cursor = cursor;
+                matches.extend(page);
+            }
+            Err(e) => error!("failed to load more results: {}", e),
+        }
+    }
+
     fn select_next(&mut self) {
-        let len = self.matches.lock().unwrap().len();
-        if len > 0 && self.selected < len - 1 {
-            self.selected += 1;
-            self.scroll = 0;
+        let len = self.active_list().0.lock().unwrap().len();
+        let selected = self.active_selected_mut();
+        if len > 0 && *selected < len - 1 {
+            *selected += 1;
+            self.follow_selection();
             self.update();
         }
     }
 
     fn select_prev(&mut self) {
-        if self.selected > 0 {
-            self.selected -= 1;
-            self.scroll = 0;
+        let selected = self.active_selected_mut();
+        if *selected > 0 {
+            *selected -= 1;
+            self.follow_selection();
             self.update();
         }
     }
 
     fn scroll(&mut self, scroll: Scroll) {
+        let pane = self.focused_mut();
         match scroll {
             Scroll::Up(s) => {
-                if self.scroll < s {
-                    self.scroll = 0;
+                if pane.scroll < s {
+                    pane.scroll = 0;
                 } else {
-                    self.scroll -= s;
+                    pane.scroll -= s;
                 }
             }
-            Scroll::Down(s) => self.scroll += s
+            Scroll::Down(s) => pane.scroll = (pane.scroll + s).min(pane.extent.get().max_scroll()),
+            Scroll::Top => pane.scroll = 0,
+            Scroll::Bottom => pane.scroll = pane.extent.get().max_scroll(),
+        }
+        self.update();
+    }
+
+    fn cycle_link(&mut self, forward: bool) {
+        self.focused_mut().cycle_link(forward);
+        self.update();
+    }
+
+    /// The reference highlighted in the focused pane
+    fn focused_link(&self) -> Option<Reference> {
+        self.focused().link()
+    }
+
+    /// Opens an entry in the focused pane, keeping history so you can go back.
+    fn visit(&mut self, model: Box<Model>) {
+        self.focused_mut().visit(model);
+        self.update();
+    }
+
+    fn back(&mut self) {
+        self.focused_mut().back();
+        self.update();
+    }
+
+    fn forward(&mut self) {
+        self.focused_mut().forward();
+        self.update();
+    }
+
+    /// Opens the focused pane's entry in a new tab and focuses it.
+    fn open_tab(&mut self) {
+        let pane = self.focused().clone();
+        self.tabs.push(Tab::new(pane));
+        self.tab = self.tabs.len() - 1;
+        self.update();
+    }
+
+    /// Opens the focused pane's entry in a new pane next to it and focuses it.
+    fn open_split(&mut self, split: SplitDir) {
+        let pane = self.focused().clone();
+        let tab = &mut self.tabs[self.tab];
+        tab.split = split;
+        tab.panes.insert(tab.focus + 1, pane);
+        tab.focus += 1;
+        self.update();
+    }
+
+    fn focus_next(&mut self) {
+        let tab = &mut self.tabs[self.tab];
+        tab.focus = (tab.focus + 1) % tab.panes.len();
+        self.update();
+    }
+
+    fn next_tab(&mut self) {
+        self.tab = (self.tab + 1) % self.tabs.len();
+        self.update();
+    }
+
+    fn prev_tab(&mut self) {
+        self.tab = (self.tab + self.tabs.len() - 1) % self.tabs.len();
+        self.update();
+    }
+
+    /// Closes the focused pane, and its tab if it was the last pane. The last pane of the last
+    /// tab is never closed.
+    fn close_pane(&mut self) {
+        let tab = &mut self.tabs[self.tab];
+        if tab.panes.len() > 1 {
+            tab.panes.remove(tab.focus);
+            if tab.focus >= tab.panes.len() {
+                tab.focus = tab.panes.len() - 1;
+            }
+        } else if self.tabs.len() > 1 {
+            self.tabs.remove(self.tab);
+            if self.tab >= self.tabs.len() {
+                self.tab = self.tabs.len() - 1;
+            }
         }
         self.update();
     }
 
     fn update(&self) {
         debug!("screen.update()");
+        // The scroll position in the titles is based on the last draw, so draw again if the
+        // entries turned out to be a different size. Once is enough: the second draw sees the
+        // sizes the first one measured.
+        if self.draw_tab() {
+            self.draw_tab();
+        }
+        if let Some(help) = &self.help {
+            let _ = self.term.draw(&HelpOverlay(help));
+            let _ = self.term.show_cursor(false);
+        }
+        let _ = self.term.present();
+        info!("done screen.update()");
+    }
+
+    /// Draws the current tab in the current layout. Returns whether any pane's entry was a
+    /// different size than when it was last drawn.
+    fn draw_tab(&self) -> bool {
         let _ = self.term.clear();
 
-        let m = Matches {
-            matches: Arc::clone(&self.matches),
-            selected: self.selected,
-        };
-        let q = Input(Arc::clone(&self.query));
-        let sel = {
-            if let Ok(matches) = self.matches.lock() {
-                if let Some(m) = matches.get(self.selected) {
-                    Some(m.clone())
-                } else {
-                    None
+        let tab = &self.tabs[self.tab];
+        let extents: Vec<Extent> = tab.panes.iter().map(|p| p.extent.get()).collect();
+        let panes: Vec<Selection> = tab
+            .panes
+            .iter()
+            .enumerate()
+            .map(|(idx, p)| Selection {
+                model: p.model.clone(),
+                view: p.view(),
+                pane: idx,
+                extent: &p.extent,
+                find: p.find.as_ref(),
+            })
+            .collect();
+        let titles: Vec<(String, Attr)> = tab
+            .panes
+            .iter()
+            .enumerate()
+            .map(|(idx, p)| {
+                let focused = idx == tab.focus && tab.panes.len() > 1;
+                match (&self.layout, &p.model) {
+                    (Layout::Querying, _) if idx == tab.focus => {
+                        (String::from("Selected"), theme::attr(Style::PaneTitle))
+                    }
+                    (_, Some(m)) => {
+                        let (text, attr) = m.display_name();
+                        if focused {
+                            (text, Attr { effect: Effect::REVERSE, ..attr })
+                        } else {
+                            (text, attr)
+                        }
+                    }
+                    (_, None) => (String::default(), Attr::default()),
                 }
-            } else {None}
+            })
+            .zip(tab.panes.iter())
+            .map(|((text, attr), p)| match p.extent.get().position(p.scroll) {
+                Some(pos) => (format!("{} ─ {}", text, pos), attr),
+                None => (text, attr),
+            })
+            .collect();
+        let wins: Vec<Win> = panes
+            .iter()
+            .zip(titles.iter())
+            .map(|(s, title)| {
+                Win::new(s)
+                    .border(true)
+                    .padding_left(1)
+                    .padding_right(1)
+                    .title(&title.0)
+                    .title_attr(title.1)
+            })
+            .collect();
+
+        match tab.split {
+            SplitDir::Vertical => {
+                self.draw_panes(wins.into_iter().fold(HSplit::default(), |split, w| split.split(w)))
+            }
+            SplitDir::Horizontal => {
+                self.draw_panes(wins.into_iter().fold(VSplit::default(), |split, w| split.split(w)))
+            }
+        }
+        tab.panes.iter().map(|p| p.extent.get()).ne(extents.into_iter())
+    }
+
+    /// Draws the screen for the current layout with `detail` holding the panes of the current tab.
+    fn draw_panes<'a>(&self, detail: impl Split + 'a) {
+        let tab_bar = TabBar {
+            titles: self.tabs.iter().map(Tab::title).collect(),
+            current: self.tab,
         };
-        let s = Selection(sel.clone(), self.scroll);
+        let tab_bar_size = if self.tabs.len() > 1 { 1 } else { 0 };
 
         match self.layout {
             Layout::Querying => {
+                let (list, selected) = self.active_list();
+                let pinned = if let Ok(pinned) = self.pinned.lock() {
+                    pinned.iter().map(|p| p.id()).collect()
+                } else {
+                    Vec::new()
+                };
+                let list_title = match self.list {
+                    List::Results => {
+                        let loaded = list.lock().map(|l| l.len()).unwrap_or(0);
+                        let total = self.paging.lock().ok().and_then(|p| p.total).unwrap_or(loaded);
+                        if loaded == 0 {
+                            String::from("Results")
+                        } else {
+                            format!("Results {}/{}", selected + 1, total)
+                        }
+                    }
+                    List::Pinned => format!("Pinned ({})", pinned.len()),
+                };
+                let m = Matches {
+                    matches: Arc::clone(list),
+                    selected,
+                    pinned,
+                    top: &self.list_top,
+                };
+                let q = Input(Arc::clone(&self.query), self.recall.searching(), self.ghost());
+
                 let split = VSplit::default()
                     .split(Win::new(&q).basis(Size::Fixed(1)))
                     .split(
@@ -292,89 +1457,210 @@ impl Screen5e {
                                     .border(true)
                                     .basis(Size::Percent(30))
                                     .margin_top(1)
-                                    .title("Results")
-                                    .title_attr(Attr::from(Color::LIGHT_GREEN)),
+                                    .title(&list_title)
+                                    .title_attr(theme::attr(Style::PaneTitle)),
                             )
                             .split(
-                                Win::new(&s)
-                                    .border(true)
+                                VSplit::default()
                                     .basis(Size::Percent(70))
-                                    .margin_top(1)
-                                    .padding_left(1)
-                                    .padding_right(1)
-                                    .title("Selected")
-                                    .title_attr(Attr::from(Color::LIGHT_GREEN)),
+                                    .split(
+                                        Win::new(&tab_bar)
+                                            .margin_top(1)
+                                            .basis(Size::Fixed(1 + tab_bar_size)),
+                                    )
+                                    .split(detail),
                             )
                             .basis(Size::Percent(100)),
                     );
-                let _ = self.term.draw(&split);
-                let _ = self.term.show_cursor(true);
+                let _ = self.term.draw(&Recording(&split, &self.hits));
+                let _ = self.term.show_cursor(self.mode != InputMode::Normal);
             }
             Layout::Selected => {
-                let title = if let Some(sl) = sel {
-                    sl.display_name()
-                } else {
-                    (String::default(), Attr::default())
+                let find_bar = FindBar {
+                    find: self.focused().find.clone(),
+                    typing: self.mode == InputMode::Find,
                 };
-                let split = Win::new(&s)
+                let find_bar_size = if find_bar.find.is_some() { 1 } else { 0 };
+                let split = VSplit::default()
                     .basis(Size::Percent(100))
-                    .border(true)
-                    .padding_left(1)
-                    .padding_right(1)
-                    .title(&title.0)
-                    .title_attr(title.1);
-                let _ = self.term.draw(&split);
-                let _ = self.term.show_cursor(false);
+                    .split(Win::new(&tab_bar).basis(Size::Fixed(tab_bar_size)))
+                    .split(detail)
+                    .split(Win::new(&find_bar).basis(Size::Fixed(find_bar_size)));
+                let _ = self.term.draw(&Recording(&split, &self.hits));
+                let _ = self.term.show_cursor(self.mode == InputMode::Find);
             }
         }
-        let _ = self.term.present();
-        info!("done screen.update()");
+    }
+}
+
+/// Looks up a cross-reference and shows it in the focused pane.
+fn follow_link(screen: &mut Screen5e, idx: impl index::Indexer, r: &Reference) {
+    match Model::lookup(idx, r) {
+        Ok(Some(m)) => screen.visit(Box::new(m)),
+        Ok(None) => warn!("no entry found for {}", r.name),
+        Err(e) => error!("failed to look up {}: {}", r.name, e),
     }
 }
 
 fn do_query(config: Config, query: &str) -> std::result::Result<(), Box<dyn Error>> {
     trace!("do_query");
-    let idx = Tantivy::new(TantivyOptions{index_dir: config.index_dir, ..TantivyOptions::default()});
-    let results = Model::indexed_query(idx.clone(), &query);
+    let idx = Engine::open(&config.backend)?;
+    let results = Model::indexed_query(idx, &query);
     println!("{:?}", results);
     Ok(())
 }
 
-fn do_reindex(config: Config) -> std::result::Result<(), Box<dyn Error>> {
-    trace!("do_query");
-    let db = DB::connect(&config.mongo_addr).expect("failed to connect to mongodb");
-    let idx = Tantivy::new(TantivyOptions{rebuild: true, index_dir: config.index_dir, ..TantivyOptions::default()});
-
-    Model::index_all(idx.clone(), &db)?;
-
+fn do_history(count: usize, session: Option<String>) -> std::result::Result<(), Box<dyn Error>> {
+    let history = History::load()?;
+    let entries: Vec<&Entry> = history
+        .entries()
+        .iter()
+        .filter(|e| session.as_ref().map_or(true, |s| *s == e.session))
+        .collect();
+    for e in &entries[entries.len().saturating_sub(count)..] {
+        println!(
+            "{}  {:<12} {}{}",
+            history::format_time(e.time),
+            e.session,
+            e.query,
+            e.selected.as_ref().map(|s| format!(" -> {}", s)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+fn do_reindex(config: Config) -> std::result::Result<(), Box<dyn Error>> {
+    trace!("do_query");
+    let db = DB::connect(&config.backend.mongo_addr).expect("failed to connect to mongodb");
+    let idx = Engine::rebuild(&config.backend)?;
+
+    Model::flush_all(idx.clone())?;
+    Model::index_all(idx, &db)?;
+
+    // Sonic otherwise waits a while before the new words are searchable
+    if config.backend.backend == Backend::Sonic {
+        backend::connect_sonic(&config.backend, Mode::Control)?.trigger(Trigger::Consolidate)?;
+    }
+
+    Ok(())
+}
+
+fn do_sonic(config: Config, action: SonicAction) -> std::result::Result<(), Box<dyn Error>> {
+    let client = backend::connect_sonic(&config.backend, Mode::Control)?;
+    match action {
+        SonicAction::Consolidate => client.trigger(Trigger::Consolidate)?,
+        SonicAction::Backup { path } => client.trigger(Trigger::Backup(&path))?,
+        SonicAction::Restore { path } => client.trigger(Trigger::Restore(&path))?,
+        SonicAction::Info => {
+            for (name, value) in client.info()? {
+                println!("{:<24} {}", name, value);
+            }
+        }
+    }
     Ok(())
 }
 
+/// The query being typed. `cursor` is a char (not byte) index into `inner`.
 #[derive(Clone,Debug)]
 struct Query {
-    inner: String
+    inner: String,
+    cursor: usize,
 }
 impl Query {
     fn new() -> Self {
-        Query {inner: String::default()}
+        Query {inner: String::default(), cursor: 0}
+    }
+    /// Replaces the query, leaving the cursor at the end
+    fn set(&mut self, s: &str) {
+        self.inner = String::from(s);
+        self.cursor = self.len();
     }
     fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
+    fn len(&self) -> usize {
+        self.inner.chars().count()
+    }
+    fn byte_idx(&self, cursor: usize) -> usize {
+        self.inner.char_indices().nth(cursor).map_or(self.inner.len(), |(i, _)| i)
+    }
+    fn char_at(&self, cursor: usize) -> Option<char> {
+        self.inner.chars().nth(cursor)
+    }
+    /// Inserts at the cursor
     fn push(&mut self, ch: char) {
-        self.inner.push(ch);
+        let idx = self.byte_idx(self.cursor);
+        self.inner.insert(idx, ch);
+        self.cursor += 1;
+    }
+    /// Inserts pasted text at the cursor. Line breaks and tabs become spaces.
+    fn push_str(&mut self, s: &str) {
+        for ch in s.chars() {
+            self.push(if ch.is_whitespace() { ' ' } else { ch });
+        }
     }
     fn backspace(&mut self) {
-        self.inner.pop();
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let idx = self.byte_idx(self.cursor);
+            self.inner.remove(idx);
+        }
+    }
+    fn delete(&mut self) {
+        if self.cursor < self.len() {
+            let idx = self.byte_idx(self.cursor);
+            self.inner.remove(idx);
+        }
     }
+    /// Deletes the word before the cursor, along with any spaces between it and the cursor.
     fn delete_word(&mut self) {
-        let mut didpop = false;
-        while !self.is_empty() {
-            if self.inner.pop().unwrap() == ' ' && didpop {
-                self.inner.push(' ');
-                break;
-            }
-            didpop = true;
+        let end = self.cursor;
+        self.word_left();
+        let (start, end) = (self.byte_idx(self.cursor), self.byte_idx(end));
+        self.inner.replace_range(start..end, "");
+    }
+    fn kill_to_start(&mut self) {
+        let idx = self.byte_idx(self.cursor);
+        self.inner.replace_range(..idx, "");
+        self.cursor = 0;
+    }
+    fn kill_to_end(&mut self) {
+        let idx = self.byte_idx(self.cursor);
+        self.inner.truncate(idx);
+    }
+    fn left(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+    fn right(&mut self) {
+        if self.cursor < self.len() {
+            self.cursor += 1;
+        }
+    }
+    fn home(&mut self) {
+        self.cursor = 0;
+    }
+    fn end(&mut self) {
+        self.cursor = self.len();
+    }
+    /// Moves to the start of the word before the cursor
+    fn word_left(&mut self) {
+        while self.cursor > 0 && self.char_at(self.cursor - 1) == Some(' ') {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && self.char_at(self.cursor - 1) != Some(' ') {
+            self.cursor -= 1;
+        }
+    }
+    /// Moves to the end of the word after the cursor
+    fn word_right(&mut self) {
+        let len = self.len();
+        while self.cursor < len && self.char_at(self.cursor) == Some(' ') {
+            self.cursor += 1;
+        }
+        while self.cursor < len && self.char_at(self.cursor) != Some(' ') {
+            self.cursor += 1;
         }
     }
 }
@@ -384,23 +1670,80 @@ impl Display for Query {
     }
 }
 
+/// Writes the query and pinned entries to the session file.
+fn save_session(session: &mut Session, query: &Mutex<Query>, pinned: &Mutex<Vec<Box<Model>>>) {
+    session.query = query.lock().unwrap().to_string();
+    session.pinned = pinned
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|m| PinnedEntry::from_model(m))
+        .collect();
+    if let Err(e) = session.save() {
+        error!("failed to save session: {}", e);
+    }
+}
+
 fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
-    let query = Arc::new(Mutex::new(Query::new()));
+    let keymap = Keymap::load()?;
+    let mut session = match Session::load(&config.session) {
+        Ok(session) => session,
+        Err(e @ SessionError::BadName(_)) => return Err(Box::new(e)),
+        Err(e) => {
+            warn!("{}, starting a fresh session", e);
+            Session::new(&config.session)
+        }
+    };
+
+    let history = History::load()?;
+
+    let mut initial_query = Query::new();
+    initial_query.set(&session.query);
+    let query = Arc::new(Mutex::new(initial_query));
     let q2 = Arc::clone(&query);
 
     let matches = Arc::new(Mutex::new(Vec::new()));
+    let paging = Arc::new(Mutex::new(Paging::default()));
+    let completion = Arc::new(Mutex::new(None));
+    let pinned = Arc::new(Mutex::new(
+        session
+            .pinned
+            .iter()
+            .filter_map(PinnedEntry::to_model)
+            .map(Box::new)
+            .collect::<Vec<Box<Model>>>(),
+    ));
+    let pinned2 = Arc::clone(&pinned);
 
     // Term is thread-safe
-    let term = Arc::new(Term::new().unwrap());
+    let term = Arc::new(Term::with_options(TermOptions::default().mouse_enabled(true)).unwrap());
 
-    let screen = Arc::new(Mutex::new(Screen5e::new(Arc::clone(&term), Arc::clone(&query), Arc::clone(&matches))));
+    let screen = Arc::new(Mutex::new(Screen5e::new(
+        Arc::clone(&term),
+        Arc::clone(&query),
+        Arc::clone(&matches),
+        Arc::clone(&paging),
+        Arc::clone(&completion),
+        pinned,
+        config.session.clone(),
+        history,
+    )));
     let sc2 = Arc::clone(&screen);
 
+    let backend = config.backend.clone();
+    let backend_kind = config.backend.backend;
+
     let _ = thread::spawn(move || {
         // NOTE: Keep these connections inside the thread. For some reason starting
         // these from the main thread and then moving them prevents tuikit from
         // receiving WINCH signals :/
-        let idx = Tantivy::new(TantivyOptions{index_dir: config.index_dir, ..TantivyOptions::default()});
+        let idx = match Engine::open(&config.backend) {
+            Ok(idx) => idx,
+            Err(e) => {
+                error!("failed to open the index: {}", e);
+                return;
+            }
+        };
         let mut last = String::default();
         loop {
             let q = if let Ok(query) = query.lock() {
@@ -411,11 +1754,13 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
 
             if q != last && !q.is_empty() {
                 debug!("query is different");
-                update_matches(idx.clone(), &q, Arc::clone(&matches));
+                update_matches(idx.clone(), &q, Arc::clone(&matches), Arc::clone(&paging));
+                update_completion(idx.clone(), &q, Arc::clone(&completion));
                 last.clear();
                 last.push_str(&q);
-                if let Ok(sc2) = sc2.lock() {
+                if let Ok(mut sc2) = sc2.lock() {
                     info!("update loop got screen lock");
+                    sc2.follow_selection();
                     sc2.update();
                     info!("releasing screen lock");
                 }
@@ -425,11 +1770,79 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
     });
 
     let th = thread::spawn(move || {
+        // Used to look up cross-references
+        let idx = match Engine::open(&backend) {
+            Ok(idx) => Some(idx),
+            Err(e) => {
+                error!("failed to open the index for looking up links: {}", e);
+                None
+            }
+        };
         if let Ok(screen) = screen.lock() {
             screen.update();
         }
+        let mut pending = None;
+        let mut clicks = Clicks::default();
         loop {
-            let action = key_to_action(term.poll_event().unwrap());
+            let ev = match pending.take() {
+                Some(ev) => ev,
+                None => term.poll_event().unwrap(),
+            };
+            let mouse_ev = clicks.decode(&ev);
+            let mut action = if let Ok(mut screen) = screen.lock() {
+                // Arrow keys and the wheel scroll the help overlay and any other key closes it
+                if screen.help.is_some() {
+                    let scroll = match ev {
+                        Event::Key(Key::Up) | Event::Key(Key::Char('k')) => Some(Scroll::Up(1)),
+                        Event::Key(Key::Down) | Event::Key(Key::Char('j')) => Some(Scroll::Down(1)),
+                        Event::Key(Key::PageUp) => Some(Scroll::Up(10)),
+                        Event::Key(Key::PageDown) => Some(Scroll::Down(10)),
+                        Event::Key(Key::Home) => Some(Scroll::Top),
+                        Event::Key(Key::End) => Some(Scroll::Bottom),
+                        _ => match mouse_ev {
+                            Some(Mouse::WheelUp) => Some(Scroll::Up(mouse::WHEEL_LINES)),
+                            Some(Mouse::WheelDown) => Some(Scroll::Down(mouse::WHEEL_LINES)),
+                            _ => None,
+                        },
+                    };
+                    match (scroll, &ev) {
+                        (Some(scroll), _) => {
+                            screen.scroll_help(scroll);
+                            continue;
+                        }
+                        // Releasing the button after a click mustn't close the overlay again
+                        (None, Event::Key(Key::MouseRelease(..)))
+                        | (None, Event::Key(Key::MouseHold(..))) => continue,
+                        (None, Event::Key(_)) => {
+                            screen.close_help();
+                            continue;
+                        }
+                        _ => {}
+                    }
+                }
+                keymap.action(&ev, mouse_ev, &screen.layout, screen.mode)
+            } else {
+                None
+            };
+
+            // Pasted text arrives as a burst of key events. Gather it up so it goes into the query
+            // in one go and line breaks in it don't trigger bindings.
+            if let Some(Action::AddChar(ch)) = action {
+                let mut text = ch.to_string();
+                while let Ok(ev) = term.peek_event(std::time::Duration::from_millis(1)) {
+                    match ev {
+                        Event::Key(Key::Char(ch)) => text.push(ch),
+                        Event::Key(Key::Enter) | Event::Key(Key::Tab) => text.push(' '),
+                        ev => {
+                            pending = Some(ev);
+                            break;
+                        }
+                    }
+                }
+                if text.chars().count() > 1 {
+                    action = Some(Action::Paste(text));
+                }
+            }
 
             debug!("loop got action");
             if let Some(a) = action {
@@ -440,15 +1853,25 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
                                 screen.set_layout(Layout::Querying);
                             } else {
                                 screen.cleanup();
+                                save_session(&mut session, &q2, &pinned2);
                                 break;
                             }
                         }
                     }
                     Action::Backspace => {
                         if let Ok(mut screen) = screen.lock() {
+                            if screen.mode == InputMode::Find {
+                                screen.find_pop();
+                                continue;
+                            }
                             if screen.layout == Layout::Selected {
                                 continue;
                             }
+                            if screen.mode == InputMode::Search {
+                                screen.recall.search_pop();
+                                screen.update();
+                                continue;
+                            }
                             q2.lock().unwrap().backspace();
                             screen.set_selected(0);
                         }
@@ -465,14 +1888,122 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
                     }
                     Action::AddChar(key) => {
                         if let Ok(mut screen) = screen.lock() {
+                            if screen.mode == InputMode::Find {
+                                screen.find_push(key);
+                                continue;
+                            }
                             if screen.layout == Layout::Selected {
                                 continue;
                             }
+                            if screen.mode == InputMode::Search {
+                                screen.recall.search_push(key);
+                                screen.update();
+                                continue;
+                            }
                             q2.lock().unwrap().push(key);
                             screen.set_selected(0);
                         }
 
                     }
+                    Action::DeleteForward => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().delete();
+                            screen.set_selected(0);
+                        }
+                    }
+                    Action::KillToStart => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().kill_to_start();
+                            screen.set_selected(0);
+                        }
+                    }
+                    Action::KillToEnd => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().kill_to_end();
+                            screen.set_selected(0);
+                        }
+                    }
+                    Action::Paste(text) => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.mode == InputMode::Find {
+                                text.chars().for_each(|ch| screen.find_push(ch));
+                                continue;
+                            }
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            if screen.mode == InputMode::Search {
+                                text.chars().for_each(|ch| screen.recall.search_push(ch));
+                                screen.update();
+                                continue;
+                            }
+                            q2.lock().unwrap().push_str(&text);
+                            screen.set_selected(0);
+                        }
+                    }
+                    Action::CursorLeft => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().left();
+                            screen.update();
+                        }
+                    }
+                    Action::CursorRight => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().right();
+                            screen.update();
+                        }
+                    }
+                    Action::WordLeft => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().word_left();
+                            screen.update();
+                        }
+                    }
+                    Action::WordRight => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().word_right();
+                            screen.update();
+                        }
+                    }
+                    Action::LineStart => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().home();
+                            screen.update();
+                        }
+                    }
+                    Action::LineEnd => {
+                        if let Ok(screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            q2.lock().unwrap().end();
+                            screen.update();
+                        }
+                    }
                     Action::ScrollUp(n) => {
                         if let Ok(mut screen) = screen.lock() {
                             screen.scroll(Scroll::Up(n));
@@ -483,11 +2014,24 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
                             screen.scroll(Scroll::Down(n));
                         }
                     }
+                    Action::ScrollTop => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.scroll(Scroll::Top);
+                        }
+                    }
+                    Action::ScrollBottom => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.scroll(Scroll::Bottom);
+                        }
+                    }
                     Action::SelectNext => {
                         if let Ok(mut screen) = screen.lock() {
                             if screen.layout == Layout::Selected {
                                 continue;
                             }
+                            if let (true, Some(idx)) = (screen.at_end_of_results(), &idx) {
+                                screen.load_more(idx.clone());
+                            }
                             screen.select_next();
                         }
                     }
@@ -499,8 +2043,170 @@ fn do_run(config: Config) -> std::result::Result<(), Box<dyn Error>> {
                             screen.select_prev();
                         }
                     }
+                    Action::TogglePin => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.toggle_pin();
+                        }
+                        // Pins outlive a crash or kill, not just a clean quit
+                        save_session(&mut session, &q2, &pinned2);
+                    }
+                    Action::ToggleList => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            screen.toggle_list();
+                        }
+                    }
+                    Action::Complete => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if screen.layout == Layout::Selected {
+                                continue;
+                            }
+                            if !screen.complete() {
+                                screen.toggle_list();
+                            }
+                        }
+                    }
+                    Action::OpenTab => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.open_tab();
+                        }
+                    }
+                    Action::OpenSplit(dir) => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.open_split(dir);
+                        }
+                    }
+                    Action::FocusNext => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.focus_next();
+                        }
+                    }
+                    Action::NextTab => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.next_tab();
+                        }
+                    }
+                    Action::PrevTab => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.prev_tab();
+                        }
+                    }
+                    Action::ClosePane => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.close_pane();
+                        }
+                    }
+                    Action::NextLink => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.cycle_link(true);
+                        }
+                    }
+                    Action::PrevLink => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.cycle_link(false);
+                        }
+                    }
+                    Action::Back => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.back();
+                        }
+                    }
+                    Action::Forward => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.forward();
+                        }
+                    }
+                    Action::FollowLink => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if let (Some(r), Some(idx)) = (screen.focused_link(), &idx) {
+                                follow_link(&mut screen, idx.clone(), &r);
+                            }
+                        }
+                    }
+                    Action::Click(row, col) => {
+                        if let Ok(mut screen) = screen.lock() {
+                            if let (Some(r), Some(idx)) = (screen.click(row, col, false), &idx) {
+                                follow_link(&mut screen, idx.clone(), &r);
+                            }
+                        }
+                    }
+                    Action::DoubleClick(row, col) => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.click(row, col, true);
+                        }
+                    }
+                    Action::NormalMode => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.set_mode(InputMode::Normal);
+                        }
+                    }
+                    Action::InsertMode => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.set_mode(InputMode::Insert);
+                        }
+                    }
+                    Action::Help => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.show_help(&keymap, backend_kind);
+                        }
+                    }
+                    Action::Find => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.start_find();
+                        }
+                    }
+                    Action::FindNext => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.step_find(true);
+                        }
+                    }
+                    Action::FindPrev => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.step_find(false);
+                        }
+                    }
+                    Action::FindAccept => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.finish_find(true);
+                        }
+                    }
+                    Action::FindCancel => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.finish_find(false);
+                        }
+                    }
+                    Action::HistoryPrev => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.history_prev();
+                        }
+                    }
+                    Action::HistoryNext => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.history_next();
+                        }
+                    }
+                    Action::HistorySearch => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.history_search();
+                        }
+                    }
+                    Action::HistoryAccept => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.finish_search(true);
+                        }
+                    }
+                    Action::HistoryCancel => {
+                        if let Ok(mut screen) = screen.lock() {
+                            screen.finish_search(false);
+                        }
+                    }
                     Action::SetLayout(l) => {
                         if let Ok(mut screen) = screen.lock() {
+                            if l == Layout::Selected && screen.layout == Layout::Querying {
+                                screen.record_query();
+                            }
                             screen.set_layout(l);
                         }
                     }
@@ -534,9 +2240,25 @@ fn main() -> std::result::Result<(), Box<dyn Error>> {
 
     let cli = Cli::from_args();
 
+    let session = match &cli.action {
+        Some(CliAction::Run { session }) => session.clone(),
+        _ => String::from(session::DEFAULT_SESSION),
+    };
+
+    let theme = Theme::load(&cli.theme, cli.color)?;
+    icons::set(cli.icons.or_else(|| theme.icons()).unwrap_or(IconSet::Emoji));
+    theme::set(theme);
+
     let config = Config {
-        mongo_addr: cli.mongo_addr,
-        index_dir: cli.index_dir,
+        backend: BackendOptions {
+            backend: cli.backend,
+            index_dir: cli.index_dir,
+            sonic_addr: cli.sonic_addr,
+            sonic_password: cli.sonic_password,
+            sonic_connections: cli.sonic_connections,
+            mongo_addr: cli.mongo_addr,
+        },
+        session,
     };
 
 
@@ -544,10 +2266,55 @@ fn main() -> std::result::Result<(), Box<dyn Error>> {
 
     match cli.action {
         Some(action) => match action {
-            CliAction::Run => do_run(config),
+            CliAction::Run { .. } => do_run(config),
             CliAction::Query { query } => do_query(config, &query),
             CliAction::Reindex => do_reindex(config),
+            CliAction::History { count, session } => do_history(count, session),
+            CliAction::Sonic(action) => do_sonic(config, action),
         },
         None => do_run(config),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::tests::FailingIndex;
+
+    #[test]
+    fn failed_completions_clear_the_suggestion() {
+        let completion = Arc::new(Mutex::new(Some((String::from("fir"), String::from("fireball")))));
+        update_completion(FailingIndex, "fire", completion.clone());
+        assert_eq!(*completion.lock().unwrap(), None);
+    }
+
+    fn find(term: &str, lines: &[&str]) -> Vec<(usize, usize, String)> {
+        let mut find = Find {
+            term: String::from(term),
+            ..Find::default()
+        };
+        find.search(&lines.iter().map(|l| String::from(*l)).collect::<Vec<_>>());
+        find.matches
+    }
+
+    #[test]
+    fn finds_every_match_ignoring_case() {
+        assert_eq!(
+            find("fire", &["Fireball", "", "a wall of fire, FIRE"]),
+            vec![
+                (0, 0, String::from("Fire")),
+                (2, 10, String::from("fire")),
+                (2, 16, String::from("FIRE")),
+            ]
+        );
+        assert!(find("", &["Fireball"]).is_empty());
+        assert!(find("ice", &["Fireball"]).is_empty());
+    }
+
+    #[test]
+    fn match_columns_are_screen_columns() {
+        // The icon takes two columns but is a single character
+        assert_eq!(find("orc", &["👹 Orc"]), vec![(0, 3, String::from("Orc"))]);
+        assert_eq!(find("b", &["日本b"]), vec![(0, 4, String::from("b"))]);
+    }
+}
@@ -1,16 +1,90 @@
+use crate::buffer::Buffer;
 use crate::db::DB;
+use crate::icons::{self, Kind};
 use crate::index::*;
+use crate::mouse;
+use crate::theme::{self, Style};
 use bson::{doc, oid::ObjectId, Document};
 use mongodb::options::FindOptions;
 use unicode_linebreak::{linebreaks, BreakOpportunity};
+use unicode_width::UnicodeWidthStr;
 // use mongodb::Database;
 use quick_error::quick_error;
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use tuikit::canvas;
 use tuikit::prelude::*;
 
+/// How an entry should be drawn in a detail pane.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct View {
+    /// Number of rows scrolled past
+    pub scroll: usize,
+    /// Index of the highlighted link, if any
+    pub link: Option<usize>,
+}
+
 pub trait ScrollDraw {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()>;
+    /// Draws the entry scrolled by `view.scroll` rows. Returns the height of the whole entry in
+    /// rows so that scrolling can be bounded.
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize>;
+}
+
+/// The height of an entry given the row a renderer finished on.
+fn content_height(row: i32, view: &View) -> usize {
+    usize::try_from(row + i32::try_from(view.scroll).unwrap()).unwrap_or(0)
+}
+
+/// A link to another entry as it appears in SRD documents.
+///
+/// Example:
+///
+/// ```text
+/// { "name": "Wizard", "url": "/api/classes/wizard" }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub url: String,
+}
+
+impl Reference {
+    fn from_doc(d: &Document) -> Option<Self> {
+        Some(Self {
+            name: String::from(d.get_str("name").ok()?),
+            url: String::from(d.get_str("url").unwrap_or_default()),
+        })
+    }
+
+    /// Collects the references held by a field, which may be a single reference or a list.
+    fn from_field(d: &Document, field: &str) -> Vec<Self> {
+        match d.get(field) {
+            Some(bson::Bson::Document(r)) => Self::from_doc(r).into_iter().collect(),
+            Some(bson::Bson::Array(refs)) => refs
+                .iter()
+                .filter_map(|r| r.as_document())
+                .filter_map(Self::from_doc)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The mtype of the referenced entry, or None if it isn't something we can show.
+    pub fn mtype(&self) -> Option<&'static str> {
+        let kind = self.url.trim_start_matches("/api/").split('/').next()?;
+        match kind {
+            "spells" => Some("spell"),
+            "monsters" => Some("monster"),
+            "classes" => Some("class"),
+            "subclasses" => Some("subclass"),
+            "conditions" => Some("condition"),
+            "magic-schools" => Some("magic_school"),
+            "equipment" => Some("equipment"),
+            "features" => Some("feature"),
+            "weapon-properties" => Some("weapon_property"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +98,7 @@ pub enum Model {
     Equipment(Equipment),
     Feature(Feature),
     Subclass(Subclass),
+    WeaponProperty(WeaponProperty),
 }
 
 quick_error! {
@@ -46,6 +121,7 @@ impl Model {
             Self::MagicSchool(m) => Ok(Box::new(m.clone())),
             Self::Equipment(m) => Ok(Box::new(m.clone())),
             Self::Feature(m) => Ok(Box::new(m.clone())),
+            Self::WeaponProperty(m) => Ok(Box::new(m.clone())),
             _ => Err(Box::new(ModelError::NoInnerIndex("Model"))),
         }
     }
@@ -66,6 +142,7 @@ impl DisplayName for Model {
             Self::Equipment(m) => m.display_name(),
             Self::Feature(m) => m.display_name(),
             Self::Subclass(m) => m.display_name(),
+            Self::WeaponProperty(m) => m.display_name(),
             Self::Unknown(m) => (
                 String::from(m.get_str("name").unwrap_or("")),
                 Attr::default(),
@@ -75,20 +152,102 @@ impl DisplayName for Model {
 }
 
 impl Model {
-    pub fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    pub fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
+        match self {
+            Model::Spell(m) => m.draw(canvas, view),
+            Model::MagicSchool(m) => m.draw(canvas, view),
+            Model::Monster(m) => m.draw(canvas, view),
+            Model::Equipment(m) => m.draw(canvas, view),
+            Model::Feature(m) => m.draw(canvas, view),
+            Model::Condition(m) => m.draw(canvas, view),
+            Model::Class(m) => m.draw(canvas, view),
+            Model::Subclass(m) => m.draw(canvas, view),
+            Model::WeaponProperty(m) => m.draw(canvas, view),
+            _ => Ok(0),
+        }
+    }
+
+    /// The entry as plain text lines, laid out as it would be drawn `width` columns wide.
+    pub fn lines(&self, width: usize) -> Vec<String> {
+        let mut buffer = Buffer::new(width);
+        let _ = self.draw(&mut buffer, &View::default());
+        buffer.lines()
+    }
+
+    /// The followable references in this entry, in the order they are drawn. A `View::link`
+    /// indexes into this list.
+    pub fn references(&self) -> Vec<Reference> {
+        let (d, fields): (&Document, &[&str]) = match self {
+            Model::Spell(m) => (&m.document, &["school", "classes", "subclasses"]),
+            Model::Monster(m) => (&m.document, &["condition_immunities"]),
+            Model::Equipment(m) => (&m.document, &["properties"]),
+            Model::Feature(m) => (&m.document, &["class", "subclass"]),
+            Model::Class(m) => (&m.document, &["subclasses"]),
+            Model::Subclass(m) => (&m.document, &["class", "spells"]),
+            _ => return Vec::new(),
+        };
+        fields
+            .iter()
+            .flat_map(|f| references_in(d, f))
+            .filter(|r| r.mtype().is_some())
+            .collect()
+    }
+
+    /// Finds the entry a reference points to by searching the index for its name.
+    pub fn lookup(s: impl Indexer, r: &Reference) -> Result<Option<Model>> {
+        let mtype = match r.mtype() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        // Keep the query parser away from punctuation in names like "Potion of Healing (greater)"
+        let qs: String = r
+            .name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+            .collect();
+        Ok(Self::indexed_query(s, &qs)?
+            .into_iter()
+            .find(|m| m.mtype() == mtype && m.name().eq_ignore_ascii_case(&r.name))
+            .map(|m| *m))
+    }
+
+    pub fn name(&self) -> String {
         match self {
-            Model::Spell(m) => m.draw(canvas, scroll),
-            Model::MagicSchool(m) => m.draw(canvas, scroll),
-            Model::Monster(m) => m.draw(canvas, scroll),
-            Model::Equipment(m) => m.draw(canvas, scroll),
-            Model::Feature(m) => m.draw(canvas, scroll),
-            Model::Condition(m) => m.draw(canvas, scroll),
-            // Model::Class(m) => m.draw(canvas, scroll),
-            _ => Ok(()),
+            Self::Spell(m) => m.name.clone(),
+            Self::Monster(m) => m.name.clone(),
+            Self::Class(m) => m.name.clone(),
+            Self::Subclass(m) => m.name.clone(),
+            Self::Condition(m) => m.name.clone(),
+            Self::MagicSchool(m) => m.name.clone(),
+            Self::Equipment(m) => m.name.clone(),
+            Self::Feature(m) => m.name.clone(),
+            Self::WeaponProperty(m) => m.name.clone(),
+            Self::Unknown(m) => String::from(m.get_str("name").unwrap_or("")),
         }
     }
 }
 
+/// Gets the references in a field. Subclass spell lists nest the reference one level down:
+///
+/// ```text
+/// "spells": [{ "prerequisites": [...], "spell": { "name": "Bless", "url": "/api/spells/bless" } }]
+/// ```
+fn references_in(d: &Document, field: &str) -> Vec<Reference> {
+    if field == "spells" {
+        d.get_array(field)
+            .map(|spells| {
+                spells
+                    .iter()
+                    .filter_map(|s| s.as_document())
+                    .flat_map(|s| Reference::from_field(s, "spell"))
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        Reference::from_field(d, field)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Spell {
     pub id: String,
@@ -124,11 +283,9 @@ pub trait ModelQuery {
         let res = d
             .with_db(|db| {
                 db.collection(&Self::Item::collection())
-                    .find(query, FindOptions::builder().build())
-                    .unwrap()
+                    .find(query, FindOptions::builder().build())?
                     .collect()
-            })
-            .unwrap();
+            })?;
         Ok(res
             .iter()
             .map(|d: &Document| Box::new(Self::Item::from(d.clone())))
@@ -145,20 +302,15 @@ pub trait ModelQuery {
         s.index_bulk(Self::all(db).unwrap())
     }
 
-    /// Performs a query in the index and returns Items. Default implementation looks up ids in the
-    /// index and then queries a database for those ids.
+    /// Performs a query in the index and returns Items. Indexes that only know ids, like sonic,
+    /// look the documents up in their document store.
     fn indexed_query(s: impl Indexer, qs: &str) -> Result<Vec<Box<Self::Item>>> {
-        unimplemented!("TODO: implement the single model query");
-        // let ids = s.query_ids(&Self::Item::collection(), qs)?;
-        //
-        // let oids: Vec<ObjectId> = ids
-        //     .iter()
-        //     .map(|sid| bson::oid::ObjectId::with_string(sid).unwrap())
-        //     .collect();
-        //
-        // let query: Document = doc! {"_id": {"$in": oids}};
-        //
-        // Self::find(db, query)
+        let mut items = Vec::new();
+        for (_mtype, bytes) in s.query(&Self::Item::collection(), qs)? {
+            let d = bson::decode_document(&mut std::io::Cursor::new(bytes))?;
+            items.push(Box::new(Self::Item::from(d)));
+        }
+        Ok(items)
     }
 }
 
@@ -320,6 +472,10 @@ impl ModelQuery for Feature {
     type Item = Feature;
 }
 
+impl ModelQuery for WeaponProperty {
+    type Item = WeaponProperty;
+}
+
 impl Collection for Model {
     fn collection() -> String {
         String::from("all")
@@ -351,6 +507,7 @@ impl Index for Model {
             Self::MagicSchool(m) => format!("{}:{}", MagicSchool::collection(), m.id()),
             Self::Equipment(m) => format!("{}:{}", Equipment::collection(), m.id()),
             Self::Feature(m) => format!("{}:{}", Feature::collection(), m.id()),
+            Self::WeaponProperty(m) => format!("{}:{}", WeaponProperty::collection(), m.id()),
             Self::Unknown(m) => String::from(m.get_str("id").unwrap_or("")),
         }
     }
@@ -365,6 +522,7 @@ impl Index for Model {
             Self::MagicSchool(m) => m.mtype(),
             Self::Equipment(m) => m.mtype(),
             Self::Feature(m) => m.mtype(),
+            Self::WeaponProperty(m) => m.mtype(),
             Self::Unknown(_) => "unknown".into(),
         }
     }
@@ -390,6 +548,7 @@ impl ToBytes for Model {
             Self::MagicSchool(m) => m.to_bytes(),
             Self::Equipment(m) => m.to_bytes(),
             Self::Feature(m) => m.to_bytes(),
+            Self::WeaponProperty(m) => m.to_bytes(),
             Self::Unknown(m) => {
                 let mut buf = Vec::new();
                 bson::encode_document(&mut buf, m).unwrap();
@@ -406,7 +565,7 @@ impl From<Document> for Model {
     }
 }
 
-type ModelQueryFn = Box<dyn (Fn(&DB) -> Vec<Box<Model>>) + Send + 'static>;
+type ModelQueryFn = Box<dyn (Fn(&DB) -> Result<Vec<Box<Model>>>) + Send + 'static>;
 
 impl ModelQuery for Model {
     type Item = Model;
@@ -421,6 +580,7 @@ impl ModelQuery for Model {
         MagicSchool::flush_all(c.clone())?;
         Equipment::flush_all(c.clone())?;
         Feature::flush_all(c.clone())?;
+        WeaponProperty::flush_all(c.clone())?;
         c.flush_all(&Self::Item::collection())
     }
 
@@ -440,57 +600,60 @@ impl ModelQuery for Model {
     fn index_all(s: impl Indexer, database: &DB) -> std::result::Result<(), IndexError> {
         let fns: Vec<ModelQueryFn> = vec![
             Box::new(|db| {
-                Spell::all(db)
-                    .unwrap()
+                Ok(Spell::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Spell(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                Monster::all(db)
-                    .unwrap()
+                Ok(Monster::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Monster(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                Class::all(db)
-                    .unwrap()
+                Ok(Class::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Class(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                Condition::all(db)
-                    .unwrap()
+                Ok(Condition::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Condition(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                MagicSchool::all(db)
-                    .unwrap()
+                Ok(MagicSchool::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::MagicSchool(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                Equipment::all(db)
-                    .unwrap()
+                Ok(Equipment::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Equipment(*s.clone())))
-                    .collect()
+                    .collect())
             }),
             Box::new(|db| {
-                Feature::all(db)
-                    .unwrap()
+                Ok(Feature::all(db)?
                     .iter()
                     .map(|s| Box::new(Model::Feature(*s.clone())))
-                    .collect()
+                    .collect())
+            }),
+            Box::new(|db| {
+                Ok(WeaponProperty::all(db)?
+                    .iter()
+                    .map(|s| Box::new(Model::WeaponProperty(*s.clone())))
+                    .collect())
             }),
         ];
         for f in fns {
-            s.index_bulk(f(database));
+            let models = f(database).map_err(|e| {
+                error!("failed to read models to index: {}", e);
+                IndexError::ProcessingError
+            })?;
+            s.index_bulk(models)?;
         }
         Ok(())
     }
@@ -500,34 +663,124 @@ impl ModelQuery for Model {
         trace!("indexed query");
         let docs = s.query(&Self::Item::collection(), qs)?;
         debug!("got {} docs", docs.len());
-        let mut results: Vec<Box<Model>> = Vec::new();
+        Ok(Model::from_docs(docs))
+    }
+}
 
+impl Model {
+    /// Queries the page of results across all types after `cursor`, moving it on. Also returns
+    /// the total number of matches if the index knows it.
+    pub fn query_page(
+        s: impl Indexer,
+        qs: &str,
+        cursor: &mut Cursor,
+    ) -> Result<(Vec<Box<Model>>, Option<usize>)> {
+        let offset = cursor.offset;
+        let page = s.query_page(&Model::collection(), qs, cursor, PAGE_SIZE)?;
+        debug!("got {} docs from {}", page.docs.len(), offset);
+        Ok((Model::from_docs(page.docs), page.total))
+    }
+
+    fn from_docs(docs: Vec<(String, Vec<u8>)>) -> Vec<Box<Model>> {
+        let mut results: Vec<Box<Model>> = Vec::new();
         for (t, doc) in docs {
-            let mut c = std::io::Cursor::new(doc);
-            match bson::decode_document(&mut c) {
-                Ok(d) => {
-                    let m = match t.as_str() {
-                        "spell" => Model::Spell(Spell::from(d.clone())),
-                        "feature" => Model::Feature(Feature::from(d.clone())),
-                        "equipment" => Model::Equipment(Equipment::from(d.clone())),
-                        "monster" => Model::Monster(Monster::from(d.clone())),
-                        "class" => Model::Class(Class::from(d.clone())),
-                        "subclass" => Model::Subclass(Subclass::from(d.clone())),
-                        "magic_school" => Model::MagicSchool(MagicSchool::from(d.clone())),
-                        // "race" => Model::Race(Race::from(d.clone())),
-                        "condition" => Model::Condition(Condition::from(d.clone())),
-                        _ => unimplemented!("dont know how to do this one")
-                    };
-                    debug!("model: {}", d);
-                    results.push(Box::new(m));
-                },
+            match Model::from_bytes(&t, doc) {
+                Ok(m) => results.push(Box::new(m)),
                 Err(e) => {
                     error!("failed to decode doc: {}", e);
                 }
             }
         }
+        results
+    }
+
+    /// Rebuilds a model from its mtype and the bytes produced by `ToBytes::to_bytes`. This is the
+    /// inverse of what gets stored in the index.
+    pub fn from_bytes(mtype: &str, bytes: Vec<u8>) -> Result<Model> {
+        let mut c = std::io::Cursor::new(bytes);
+        let d = bson::decode_document(&mut c)?;
+        debug!("model: {}", d);
+        Ok(match mtype {
+            "spell" => Model::Spell(Spell::from(d)),
+            "feature" => Model::Feature(Feature::from(d)),
+            "equipment" => Model::Equipment(Equipment::from(d)),
+            "monster" => Model::Monster(Monster::from(d)),
+            "class" => Model::Class(Class::from(d)),
+            "subclass" => Model::Subclass(Subclass::from(d)),
+            "magic_school" => Model::MagicSchool(MagicSchool::from(d)),
+            // "race" => Model::Race(Race::from(d)),
+            "condition" => Model::Condition(Condition::from(d)),
+            "weapon_property" => Model::WeaponProperty(WeaponProperty::from(d)),
+            _ => Model::Unknown(d),
+        })
+    }
+}
+
+/// The mtype of the models kept in a MongoDB collection.
+fn collection_mtype(collection: &str) -> Option<&'static str> {
+    let mtypes = [
+        (Spell::collection(), "spell"),
+        (Monster::collection(), "monster"),
+        (Class::collection(), "class"),
+        (Subclass::collection(), "subclass"),
+        (Condition::collection(), "condition"),
+        (MagicSchool::collection(), "magic_school"),
+        (Equipment::collection(), "equipment"),
+        (Feature::collection(), "feature"),
+        (WeaponProperty::collection(), "weapon_property"),
+    ];
+    mtypes
+        .iter()
+        .find(|(c, _)| c == collection)
+        .map(|(_, t)| *t)
+}
+
+/// Looks up index ids of the form `<collection>:<objectid>` in MongoDB.
+impl DocumentStore for DB {
+    fn documents(&self, ids: &[String]) -> std::result::Result<Vec<(String, Vec<u8>)>, IndexError> {
+        let mut by_collection: HashMap<String, Vec<bson::Bson>> = HashMap::new();
+        for id in ids {
+            let mut parts = id.splitn(2, ':');
+            match (parts.next(), parts.next().map(ObjectId::with_string)) {
+                (Some(col), Some(Ok(oid))) => by_collection
+                    .entry(String::from(col))
+                    .or_default()
+                    .push(bson::Bson::ObjectId(oid)),
+                _ => warn!("skipping malformed id {}", id),
+            }
+        }
 
-        Ok(results)
+        let mut found = HashMap::new();
+        for (col, oids) in by_collection {
+            let mtype = match collection_mtype(&col) {
+                Some(t) => t,
+                None => {
+                    warn!("no models in collection {}", col);
+                    continue;
+                }
+            };
+            let docs = self
+                .with_db(|db| {
+                    db.collection(&col)
+                        .find(doc! {"_id": {"$in": oids}}, FindOptions::builder().build())?
+                        .collect()
+                })
+                .map_err(|e| {
+                    error!("failed to look up documents in {}: {}", col, e);
+                    IndexError::ProcessingError
+                })?;
+            for d in docs {
+                let id = match d.get_object_id("_id") {
+                    Ok(oid) => format!("{}:{}", col, oid.to_hex()),
+                    Err(_) => continue,
+                };
+                let mut bytes = Vec::new();
+                if bson::encode_document(&mut bytes, &d).is_ok() {
+                    found.insert(id, (String::from(mtype), bytes));
+                }
+            }
+        }
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
     }
 }
 
@@ -631,6 +884,7 @@ fn draw_actions(
     actions: &[bson::Bson],
     width: usize,
     row: i32,
+    col: usize,
 ) -> canvas::Result<i32> {
     let mut idx = 0;
     for s in actions {
@@ -650,10 +904,10 @@ fn draw_actions(
             if let Ok(desc) = d.get_str("desc") {
                 line.push_str(desc);
                 for l in break_at(&line, width) {
-                    idx += print(canvas, row + idx, 0, l, Attr::default()).unwrap();
+                    idx += print(canvas, row + idx, col, l, Attr::default()).unwrap();
                 }
 
-                let _ = print(canvas, starting_row, 0, &name, Attr::from(Effect::BOLD));
+                let _ = print(canvas, starting_row, col, &name, theme::attr(Style::Label));
             }
             idx += 1;
         }
@@ -662,29 +916,29 @@ fn draw_actions(
 }
 
 impl ScrollDraw for Spell {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
         let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
-        row += print(canvas, row, col, &self.name, Attr {fg: Color::AnsiValue(202), effect: Effect::BOLD, ..Attr::default()}).unwrap();
-
-        if let Ok(level) = self.document.get_i32("level") {
-            let level = if level == 0 {
-                String::from("cantrip")
-            } else {
-                format!("Level {}", level)
-            };
-            if let Ok(school) = self.document.get_document("school") {
-                let school_name = school.get_str("name").unwrap();
-                row += print(
-                    canvas,
-                    row,
-                    col,
-                    &format!("{} {}", level, school_name),
-                    Attr::default(),
-                )
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        let mut link = 0;
+        row += print(canvas, row, col, &self.name, theme::attr(Style::SpellTitle)).unwrap();
+
+        let level = match self.document.get_i32("level") {
+            Ok(0) => Some(String::from("cantrip")),
+            Ok(level) => Some(format!("Level {}", level)),
+            Err(_) => None,
+        };
+        // The school is drawn even without a level since `references` always lists it
+        let school = Reference::from_field(&self.document, "school");
+        if let Some(level) = &level {
+            let _ = print(canvas, row, col, level, Attr::default());
+        }
+        if !school.is_empty() {
+            let indent = level.as_ref().map_or(0, |l| l.width() + 1);
+            row += print_links(canvas, row, col + indent, width, &school, None, view, &mut link)
                 .unwrap();
-            }
+        } else if level.is_some() {
+            row += 1;
         }
 
         row += 1;
@@ -734,18 +988,23 @@ impl ScrollDraw for Spell {
             row += print_with_title(canvas, row, col, width, &s, Some("Duration:")).unwrap()
         }
 
-        if let Ok(classes) = self.document.get_array("classes") {
-            row += print_with_title(
+        let classes = Reference::from_field(&self.document, "classes");
+        if !classes.is_empty() {
+            row += print_links(canvas, row, col, width, &classes, Some("Classes:"), view, &mut link)
+                .unwrap();
+        }
+
+        let subclasses = Reference::from_field(&self.document, "subclasses");
+        if !subclasses.is_empty() {
+            row += print_links(
                 canvas,
                 row,
                 col,
                 width,
-                &classes
-                    .iter()
-                    .map(|c| c.as_document().unwrap().get_str("name").unwrap())
-                    .collect::<Vec<&str>>()
-                    .join(", "),
-                Some("Classes:"),
+                &subclasses,
+                Some("Subclasses:"),
+                view,
+                &mut link,
             )
             .unwrap();
         }
@@ -775,7 +1034,7 @@ impl ScrollDraw for Spell {
             .unwrap()
         }
 
-        Ok(())
+        Ok(content_height(row, view))
     }
 }
 
@@ -812,12 +1071,61 @@ fn print_with_title(
         idx += print(canvas, row + idx, col, l, Attr::default()).unwrap();
     }
     if let Some(t) = title {
-        let _ = print(canvas, row, col, t, Attr::from(Effect::BOLD)).unwrap();
+        let _ = print(canvas, row, col, t, theme::attr(Style::Label)).unwrap();
     }
 
     Ok(idx)
 }
 
+/// Prints references as links, wrapping as needed. Only references we can follow count as links;
+/// `link` is the index of the next link in the entry and is advanced past the ones printed here so
+/// that it lines up with `Model::references`. Returns the number of rows printed.
+#[allow(clippy::too_many_arguments)]
+fn print_links(
+    canvas: &mut dyn Canvas,
+    row: i32,
+    col: usize,
+    width: usize,
+    refs: &[Reference],
+    title: Option<&str>,
+    view: &View,
+    link: &mut usize,
+) -> canvas::Result<i32> {
+    let mut idx = 0;
+    let mut x = 0;
+    if let Some(t) = title {
+        let _ = print(canvas, row, col, t, theme::attr(Style::Label));
+        x = t.width() + 1;
+    }
+    for (i, r) in refs.iter().enumerate() {
+        let sep = if i + 1 < refs.len() { ", " } else { "" };
+        if x > 0 && x + r.name.width() + sep.len() > width {
+            idx += 1;
+            x = 0;
+        }
+        let attr = if r.mtype().is_none() {
+            Attr::default()
+        } else if view.link == Some(*link) {
+            theme::attr(Style::SelectedLink)
+        } else {
+            theme::attr(Style::Link)
+        };
+        if r.mtype().is_some() {
+            let l = *link;
+            let _ = mouse::tagged(
+                |t| t.link = Some(l),
+                || print(canvas, row + idx, col + x, &r.name, attr),
+            );
+            *link += 1;
+        } else {
+            let _ = print(canvas, row + idx, col + x, &r.name, attr);
+        }
+        let _ = print(canvas, row + idx, col + x + r.name.width(), sep, Attr::default());
+        x += r.name.width() + sep.len();
+    }
+    Ok(idx + 1)
+}
+
 fn calc_modifier(val: i32) -> i32 {
     (val - 10) / 2
 }
@@ -841,13 +1149,44 @@ fn format_stat(val: i32) -> String {
 //        ___STR___   DEX      CON      INT      WIS      CHA
 //          27(+8)  14(+12)   5(+0)      16(+3)      15(+2)      19(+4)
 
-/// Monster stat blocks get up to two panels.
-/// This is the draw fn for left block
+/// Panes at least this wide show monster stat blocks in two columns like the printed book.
+const TWO_COLUMN_WIDTH: usize = 120;
+const COLUMN_GAP: usize = 3;
+
+/// Monster stat blocks get up to two panels. On wide panes the stats and traits go in the left
+/// column and the actions in the right, otherwise the actions follow the traits.
 impl ScrollDraw for Monster {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
-        let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
+        let row: i32 = -(i32::try_from(view.scroll).unwrap());
+
+        let rows = if width >= TWO_COLUMN_WIDTH {
+            let col_width = (width - COLUMN_GAP) / 2;
+            let left = self.draw_stat_block(canvas, row, 0, col_width, view)?;
+            let right = self.draw_action_block(canvas, row, col_width + COLUMN_GAP, col_width)?;
+            left.max(right)
+        } else {
+            let stats = self.draw_stat_block(canvas, row, 0, width, view)?;
+            stats + self.draw_action_block(canvas, row + stats, 0, width)?
+        };
+
+        Ok(content_height(row + rows, view))
+    }
+}
+
+impl Monster {
+    /// This is the draw fn for the left block: everything from the name down to the special
+    /// traits. Returns the number of rows drawn.
+    fn draw_stat_block(
+        &self,
+        canvas: &mut dyn Canvas,
+        start: i32,
+        col: usize,
+        width: usize,
+        view: &View,
+    ) -> canvas::Result<i32> {
+        let mut row = start;
+        let mut link = 0;
 
         // Name
         row += print(
@@ -855,10 +1194,7 @@ impl ScrollDraw for Monster {
             row,
             col,
             &self.name,
-            Attr {
-                effect: Effect::BOLD,
-                ..Attr::default()
-            },
+            theme::attr(Style::Title),
         )
         .unwrap();
 
@@ -883,10 +1219,7 @@ impl ScrollDraw for Monster {
             row,
             col,
             &"~".repeat(width),
-            Attr {
-                effect: Effect::BOLD,
-                ..Attr::default()
-            },
+            theme::attr(Style::Rule),
         )
         .unwrap();
 
@@ -941,17 +1274,14 @@ impl ScrollDraw for Monster {
             row,
             col,
             &"~".repeat(width),
-            Attr {
-                effect: Effect::BOLD,
-                ..Attr::default()
-            },
+            theme::attr(Style::Rule),
         )
         .unwrap();
 
         // STR, DEX, CON, INT, WIS, CHA
         let stats = vec!["STR", "DEX", "CON", "INT", "WIS", "CHA"];
         let spacing = 6;
-        row += print(canvas, row, col, &(" ".repeat(spacing/2) + &stats.join(&" ".repeat(spacing))), Attr::from(Effect::BOLD)).unwrap();
+        row += print(canvas, row, col, &(" ".repeat(spacing/2) + &stats.join(&" ".repeat(spacing))), theme::attr(Style::Label)).unwrap();
 
         let attrs = vec!["strength", "dexterity", "constitution", "intelligence", "wisdom", "charisma"];
         let mut s = Vec::default();
@@ -969,10 +1299,7 @@ impl ScrollDraw for Monster {
             row,
             col,
             &"~".repeat(width),
-            Attr {
-                effect: Effect::BOLD,
-                ..Attr::default()
-            },
+            theme::attr(Style::Rule),
         )
         .unwrap();
 
@@ -1090,22 +1417,19 @@ impl ScrollDraw for Monster {
             }
         }
 
-        if let Ok(cond) = self.document.get_array("condition_immunities") {
-            if !cond.is_empty() {
-                row += print_with_title(
-                    canvas,
-                    row,
-                    col,
-                    width,
-                    &cond
-                        .iter()
-                        .map(|v| v.as_document().unwrap().get_str("name").unwrap())
-                        .collect::<Vec<&str>>()
-                        .join(", "),
-                    Some("Condition Immunities:"),
-                )
-                .unwrap();
-            }
+        let cond = Reference::from_field(&self.document, "condition_immunities");
+        if !cond.is_empty() {
+            row += print_links(
+                canvas,
+                row,
+                col,
+                width,
+                &cond,
+                Some("Condition Immunities:"),
+                view,
+                &mut link,
+            )
+            .unwrap();
         }
 
         // Senses
@@ -1154,18 +1478,29 @@ impl ScrollDraw for Monster {
             row,
             col,
             &"~".repeat(width),
-            Attr {
-                effect: Effect::BOLD,
-                ..Attr::default()
-            },
+            theme::attr(Style::Rule),
         )
         .unwrap();
 
         // Special abilities
         if let Ok(special) = self.document.get_array("special_abilities") {
-            row += draw_actions(canvas, special, width, row).unwrap();
+            row += draw_actions(canvas, special, width, row, col).unwrap();
         }
 
+        Ok(row - start)
+    }
+
+    /// This is the draw fn for the right block: actions, reactions and legendary actions. Returns
+    /// the number of rows drawn.
+    fn draw_action_block(
+        &self,
+        canvas: &mut dyn Canvas,
+        start: i32,
+        col: usize,
+        width: usize,
+    ) -> canvas::Result<i32> {
+        let mut row = start;
+
         // Actions
         if let Ok(actions) = self.document.get_array("actions") {
             row += print(
@@ -1173,14 +1508,23 @@ impl ScrollDraw for Monster {
                 row,
                 col,
                 "Actions",
-                Attr {
-                    effect: Effect::BOLD,
-                    fg: Color::RED,
-                    ..Attr::default()
-                },
+                theme::attr(Style::Heading),
             )
             .unwrap();
-            row += draw_actions(canvas, actions, width, row).unwrap();
+            row += draw_actions(canvas, actions, width, row, col).unwrap();
+        }
+
+        // Reactions
+        if let Ok(reactions) = self.document.get_array("reactions") {
+            row += print(
+                canvas,
+                row,
+                col,
+                "Reactions",
+                theme::attr(Style::Heading),
+            )
+            .unwrap();
+            row += draw_actions(canvas, reactions, width, row, col).unwrap();
         }
 
         // Legendary Actions
@@ -1190,17 +1534,13 @@ impl ScrollDraw for Monster {
                 row,
                 col,
                 "Legendary Actions",
-                Attr {
-                    effect: Effect::BOLD,
-                    fg: Color::RED,
-                    ..Attr::default()
-                },
+                theme::attr(Style::Heading),
             )
             .unwrap();
-            row += draw_actions(canvas, actions, width, row).unwrap();
+            row += draw_actions(canvas, actions, width, row, col).unwrap();
         }
 
-        Ok(())
+        Ok(row - start)
     }
 }
 
@@ -1268,6 +1608,14 @@ pub struct Feature {
     document: Document,
 }
 
+#[derive(Debug, Clone)]
+pub struct WeaponProperty {
+    id: String,
+    name: String,
+    desc: Vec<String>,
+    document: Document,
+}
+
 impl Collection for MagicSchool {
     fn collection() -> String {
         String::from("magic-schools")
@@ -1313,18 +1661,17 @@ impl From<Document> for MagicSchool {
 }
 
 impl ScrollDraw for MagicSchool {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
         let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
-        let _ = print(canvas, row, col, &self.name, Attr::from(Effect::BOLD));
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        let _ = print(canvas, row, col, &self.name, theme::attr(Style::Title));
         row += 1;
 
         trace!("drawing");
-        let _ = print_with_title(canvas, row, col, width, &self.desc, None);
-        row += 1;
+        row += print_with_title(canvas, row, col, width, &self.desc, None)?;
 
-        Ok(())
+        Ok(content_height(row, view))
     }
 }
 
@@ -1422,11 +1769,11 @@ impl Index for Equipment {
 }
 
 impl ScrollDraw for Equipment {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
         let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
-        row += print(canvas, row, col, &self.name, Attr::from(Effect::BOLD)).unwrap();
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title)).unwrap();
 
         if let Ok(dmg) = self.document.get_document("damage") {
             let mut d = String::default();
@@ -1443,10 +1790,16 @@ impl ScrollDraw for Equipment {
             if let Ok(dt) = dmg.get_document("damage_type") {
                 d.push_str(dt.get_str("name").unwrap());
             }
-            print(canvas, row, col, &d, Attr::default())?;
+            row += print(canvas, row, col, &d, Attr::default())?;
         }
 
-        Ok(())
+        let properties = Reference::from_field(&self.document, "properties");
+        if !properties.is_empty() {
+            let mut link = 0;
+            row += print_links(canvas, row, col, width, &properties, Some("Properties:"), view, &mut link)?;
+        }
+
+        Ok(content_height(row, view))
     }
 }
 
@@ -1485,28 +1838,97 @@ impl Index for Feature {
 }
 
 impl ScrollDraw for Feature {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
         let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
-        row += print(canvas, row, col, &self.name, Attr::from(Effect::BOLD))?;
-        if let Ok(class) = self.document.get_document("class") {
-            let mut s = Vec::default();
-            if let Ok(level) = self.document.get_i32("level") {
-                s.push(format!("Level {}", level));
-            }
-            if let Ok(name) = class.get_str("name") {
-                s.push(String::from(name));
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        let mut link = 0;
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title))?;
+        if let Ok(level) = self.document.get_i32("level") {
+            row += print(canvas, row, col, &format!("Level {}", level), Attr::default())?;
+        }
+        let class = Reference::from_field(&self.document, "class");
+        if !class.is_empty() {
+            row += print_links(canvas, row, col, width, &class, Some("Class:"), view, &mut link)?;
+        }
+        let subclass = Reference::from_field(&self.document, "subclass");
+        if !subclass.is_empty() {
+            row += print_links(canvas, row, col, width, &subclass, Some("Subclass:"), view, &mut link)?;
+        }
+        row += 1;
+
+        for line in &self.desc {
+            for l in break_at(line, width) {
+                let _ = print(canvas, row, col, &l, Attr::default());
+                row += 1;
             }
-            if let Ok(subclass) = self.document.get_document("subclass") {
-                if let Ok(name) = subclass.get_str("name") {
-                    s.push(format!("({})", name));
-                }
+        }
+        row += 1;
+
+        Ok(content_height(row, view))
+    }
+}
+
+impl ScrollDraw for Condition {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
+        let (width, _height) = canvas.size()?;
+        let col = 0;
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title))?;
+
+        for line in &self.desc {
+            for l in break_at(line, width) {
+                let _ = print(canvas, row, col, &l, Attr::default());
+                row += 1;
             }
-            row += print(canvas, row, col, &s.join(" "), Attr::default())?;
         }
         row += 1;
 
+        Ok(content_height(row, view))
+    }
+}
+
+impl Collection for WeaponProperty {
+    fn collection() -> String {
+        String::from("weapon-properties")
+    }
+}
+
+impl Index for WeaponProperty {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+    fn mtype(&self) -> String {
+        "weapon_property".into()
+    }
+
+    fn tuples(&self) -> Vec<(String, String, String, String)> {
+        let mut t: Vec<(String, String, String, String)> = Vec::default();
+        t.push((
+            Self::collection(),
+            String::from("name"),
+            self.id(),
+            self.name.clone(),
+        ));
+        for d in &self.desc {
+            t.push((
+                Self::collection(),
+                String::from("desc"),
+                self.id(),
+                d.to_string(),
+            ));
+        }
+        t
+    }
+}
+
+impl ScrollDraw for WeaponProperty {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
+        let (width, _height) = canvas.size()?;
+        let col = 0;
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title))?;
+
         for line in &self.desc {
             for l in break_at(line, width) {
                 let _ = print(canvas, row, col, &l, Attr::default());
@@ -1515,16 +1937,58 @@ impl ScrollDraw for Feature {
         }
         row += 1;
 
-        Ok(())
+        Ok(content_height(row, view))
     }
 }
 
-impl ScrollDraw for Condition {
-    fn draw(&self, canvas: &mut dyn Canvas, scroll: usize) -> canvas::Result<()> {
+impl ScrollDraw for Class {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
         let (width, _height) = canvas.size()?;
         let col = 0;
-        let mut row: i32 = -(i32::try_from(scroll).unwrap());
-        row += print(canvas, row, col, &self.name, Attr::from(Effect::BOLD))?;
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        let mut link = 0;
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title))?;
+        row += 1;
+
+        if let Ok(hit_die) = self.document.get_i32("hit_die") {
+            row += print_with_title(canvas, row, col, width, &format!("d{}", hit_die), Some("Hit Die:"))?;
+        }
+
+        for (field, title) in &[("proficiencies", "Proficiencies:"), ("saving_throws", "Saving Throws:")] {
+            let names = Reference::from_field(&self.document, field)
+                .into_iter()
+                .map(|r| r.name)
+                .collect::<Vec<String>>();
+            if !names.is_empty() {
+                row += print_with_title(canvas, row, col, width, &names.join(", "), Some(title))?;
+            }
+        }
+
+        let subclasses = Reference::from_field(&self.document, "subclasses");
+        if !subclasses.is_empty() {
+            row += print_links(canvas, row, col, width, &subclasses, Some("Subclasses:"), view, &mut link)?;
+        }
+
+        Ok(content_height(row, view))
+    }
+}
+
+impl ScrollDraw for Subclass {
+    fn draw(&self, canvas: &mut dyn Canvas, view: &View) -> canvas::Result<usize> {
+        let (width, _height) = canvas.size()?;
+        let col = 0;
+        let mut row: i32 = -(i32::try_from(view.scroll).unwrap());
+        let mut link = 0;
+        row += print(canvas, row, col, &self.name, theme::attr(Style::Title))?;
+
+        let class = Reference::from_field(&self.document, "class");
+        if !class.is_empty() {
+            row += print_links(canvas, row, col, width, &class, Some("Class:"), view, &mut link)?;
+        }
+        if let Ok(flavor) = self.document.get_str("subclass_flavor") {
+            row += print(canvas, row, col, flavor, Attr::default())?;
+        }
+        row += 1;
 
         for line in &self.desc {
             for l in break_at(line, width) {
@@ -1534,62 +1998,119 @@ impl ScrollDraw for Condition {
         }
         row += 1;
 
-        Ok(())
+        let spells = references_in(&self.document, "spells");
+        if !spells.is_empty() {
+            row += print_links(canvas, row, col, width, &spells, Some("Spells:"), view, &mut link)?;
+        }
+
+        Ok(content_height(row, view))
     }
 }
 
 impl DisplayName for Spell {
     fn display_name(&self) -> (String, Attr) {
-        (format!("🔮 {}", self.name), Attr::from(Color::MAGENTA))
+        (icons::label(Kind::Spell, &self.name), theme::attr(Style::Spell))
     }
 }
 impl DisplayName for Condition {
     fn display_name(&self) -> (String, Attr) {
-        (format!("💢 {}", self.name), Attr::default())
+        (icons::label(Kind::Condition, &self.name), theme::attr(Style::Condition))
     }
 }
 impl DisplayName for Class {
     fn display_name(&self) -> (String, Attr) {
-        (format!("👤 {}", self.name), Attr::from(Color::BLUE))
+        (icons::label(Kind::Class, &self.name), theme::attr(Style::Class))
     }
 }
 impl DisplayName for Monster {
     fn display_name(&self) -> (String, Attr) {
-        (format!("👹 {}", self.name), Attr::from(Color::RED))
+        (icons::label(Kind::Monster, &self.name), theme::attr(Style::Monster))
     }
 }
 impl DisplayName for MagicSchool {
     fn display_name(&self) -> (String, Attr) {
         (
-            format!(" {}", self.document.get_str("name").unwrap()),
-            Attr::from(Color::GREEN),
+            icons::label(Kind::MagicSchool, self.document.get_str("name").unwrap()),
+            theme::attr(Style::MagicSchool),
         )
     }
 }
 impl DisplayName for Equipment {
     fn display_name(&self) -> (String, Attr) {
         (
-            format!("🏹 {}", self.document.get_str("name").unwrap()),
-            Attr::from(Color::LIGHT_YELLOW),
+            icons::label(Kind::Equipment, self.document.get_str("name").unwrap()),
+            theme::attr(Style::Equipment),
         )
     }
 }
 impl DisplayName for Feature {
     fn display_name(&self) -> (String, Attr) {
         (
-            format!("💡 {}", self.document.get_str("name").unwrap()),
-            Attr::from(Color::LIGHT_GREEN),
+            icons::label(Kind::Feature, self.document.get_str("name").unwrap()),
+            theme::attr(Style::Feature),
         )
     }
 }
+// Weapon properties only come up from equipment, so they share its look
+impl DisplayName for WeaponProperty {
+    fn display_name(&self) -> (String, Attr) {
+        (icons::label(Kind::Equipment, &self.name), theme::attr(Style::Equipment))
+    }
+}
 impl DisplayName for Subclass {
     fn display_name(&self) -> (String, Attr) {
         (
-            format!("👤 {}", self.document.get_str("name").unwrap()),
-            Attr::from(Color::LIGHT_GREEN),
+            icons::label(Kind::Subclass, self.document.get_str("name").unwrap()),
+            theme::attr(Style::Subclass),
         )
     }
 }
 
-impl_From!(for Spell, Monster, Condition, Class, Subclass, Race, Feature);
-impl_ToBytes!(for Spell, Monster, Condition, Class, Subclass, Race, Feature, Equipment, MagicSchool);
+impl_From!(for Spell, Monster, Condition, Class, Subclass, Race, Feature, WeaponProperty);
+impl_ToBytes!(for Spell, Monster, Condition, Class, Subclass, Race, Feature, Equipment, MagicSchool, WeaponProperty);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::tests::FailingIndex;
+
+    #[test]
+    fn failed_page_reports_the_index_error() {
+        let mut cursor = Cursor { offset: PAGE_SIZE, ..Cursor::default() };
+        let err = Model::query_page(FailingIndex, "fire", &mut cursor).err().unwrap();
+        assert_eq!(err.to_string(), "Error processing index request");
+    }
+
+    fn reference(name: &str, url: &str) -> Reference {
+        Reference {
+            name: String::from(name),
+            url: String::from(url),
+        }
+    }
+
+    #[test]
+    fn failed_lookup_reports_the_index_error() {
+        let r = reference("Fireball", "/api/spells/fireball");
+        let err = Model::lookup(FailingIndex, &r).err().unwrap();
+        assert_eq!(err.to_string(), "Error processing index request");
+    }
+
+    #[test]
+    fn weapon_properties_are_followable() {
+        assert_eq!(reference("Finesse", "/api/weapon-properties/finesse").mtype(), Some("weapon_property"));
+        assert_eq!(reference("Elf", "/api/races/elf").mtype(), None);
+    }
+
+    #[test]
+    fn spell_school_is_a_link_without_a_level() {
+        let spell = Model::Spell(Spell::from(doc! {
+            "_id": ObjectId::new().unwrap(),
+            "name": "Odd Spell",
+            "school": { "name": "Évocation", "url": "/api/magic-schools/evocation" },
+        }));
+        assert_eq!(spell.references().len(), 1);
+        let mut buffer = Buffer::new(40);
+        spell.draw(&mut buffer, &View::default()).unwrap();
+        assert_eq!(buffer.lines()[1].trim_end(), "Évocation");
+    }
+}
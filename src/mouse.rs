@@ -0,0 +1,188 @@
+use std::cell::{self, RefCell};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tuikit::canvas;
+use tuikit::prelude::*;
+use unicode_width::UnicodeWidthChar;
+
+/// Lines scrolled per wheel notch
+pub const WHEEL_LINES: usize = 3;
+
+/// Longest time between two clicks on the same cell for them to count as a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Mouse input, decoded from the button presses tuikit reports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mouse {
+    /// Left click at (row, col)
+    Click(usize, usize),
+    /// Second left click on the same cell soon after the first
+    DoubleClick(usize, usize),
+    WheelUp,
+    WheelDown,
+}
+
+/// Tells clicks from double clicks by remembering the last left click.
+#[derive(Debug, Default)]
+pub struct Clicks {
+    last: Option<(Instant, u16, u16)>,
+}
+
+impl Clicks {
+    pub fn decode(&mut self, ev: &Event) -> Option<Mouse> {
+        match ev {
+            Event::Key(Key::MousePress(MouseButton::WheelUp, _, _)) => Some(Mouse::WheelUp),
+            Event::Key(Key::MousePress(MouseButton::WheelDown, _, _)) => Some(Mouse::WheelDown),
+            Event::Key(Key::MousePress(MouseButton::Left, row, col)) => {
+                let now = Instant::now();
+                let double = match self.last {
+                    Some((at, r, c)) => (r, c) == (*row, *col) && now - at <= DOUBLE_CLICK,
+                    None => false,
+                };
+                if double {
+                    // A third click starts over rather than making another double click
+                    self.last = None;
+                    Some(Mouse::DoubleClick(*row as usize, *col as usize))
+                } else {
+                    self.last = Some((now, *row, *col));
+                    Some(Mouse::Click(*row as usize, *col as usize))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What is drawn at a screen cell, as far as clicking on it is concerned.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Target {
+    /// Row of the results list
+    pub result: Option<usize>,
+    /// Detail pane of the current tab
+    pub pane: Option<usize>,
+    /// Cross-reference within the pane, an index into `Model::references`
+    pub link: Option<usize>,
+}
+
+thread_local! {
+    static TARGET: cell::Cell<Target> = cell::Cell::new(Target::default());
+}
+
+/// Runs `f` with the target adjusted by `update`. Anything printed inside `f` is recorded as
+/// belonging to that target when drawn through a `Recording`.
+pub fn tagged<T>(update: impl FnOnce(&mut Target), f: impl FnOnce() -> T) -> T {
+    let prev = TARGET.with(cell::Cell::get);
+    let mut next = prev;
+    update(&mut next);
+    TARGET.with(|t| t.set(next));
+    let r = f();
+    TARGET.with(|t| t.set(prev));
+    r
+}
+
+/// Targets by screen position, collected while drawing.
+#[derive(Debug, Default)]
+pub struct HitMap {
+    cells: HashMap<(usize, usize), Target>,
+}
+
+impl HitMap {
+    pub fn get(&self, row: usize, col: usize) -> Target {
+        self.cells.get(&(row, col)).cloned().unwrap_or_default()
+    }
+
+    fn record(&mut self, row: usize, col: usize) {
+        let target = TARGET.with(cell::Cell::get);
+        if target != Target::default() {
+            self.cells.insert((row, col), target);
+        }
+    }
+}
+
+/// Forwards to the terminal's canvas, noting the target of every cell printed.
+struct Recorder<'a> {
+    canvas: &'a mut dyn Canvas,
+    hits: &'a mut HitMap,
+}
+
+impl<'a> Canvas for Recorder<'a> {
+    fn size(&self) -> canvas::Result<(usize, usize)> {
+        self.canvas.size()
+    }
+
+    fn clear(&mut self) -> canvas::Result<()> {
+        self.canvas.clear()
+    }
+
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) -> canvas::Result<usize> {
+        self.hits.record(row, col);
+        self.canvas.put_cell(row, col, cell)
+    }
+
+    fn print_with_attr(
+        &mut self,
+        row: usize,
+        col: usize,
+        content: &str,
+        attr: Attr,
+    ) -> canvas::Result<usize> {
+        let mut c = col;
+        for ch in content.chars() {
+            let w = ch.width().unwrap_or(0);
+            for i in 0..w {
+                self.hits.record(row, c + i);
+            }
+            c += w;
+        }
+        self.canvas.print_with_attr(row, col, content, attr)
+    }
+
+    fn set_cursor(&mut self, row: usize, col: usize) -> canvas::Result<()> {
+        self.canvas.set_cursor(row, col)
+    }
+
+    fn show_cursor(&mut self, show: bool) -> canvas::Result<()> {
+        self.canvas.show_cursor(show)
+    }
+}
+
+/// Draws a widget while rebuilding the hit map.
+pub struct Recording<'a>(pub &'a dyn Draw, pub &'a RefCell<HitMap>);
+
+impl<'a> Draw for Recording<'a> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> canvas::Result<()> {
+        let mut hits = self.1.borrow_mut();
+        hits.cells.clear();
+        self.0.draw(&mut Recorder {
+            canvas,
+            hits: &mut hits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(button: MouseButton, row: u16, col: u16) -> Event {
+        Event::Key(Key::MousePress(button, row, col))
+    }
+
+    #[test]
+    fn second_click_on_a_cell_is_a_double_click() {
+        let mut clicks = Clicks::default();
+        assert_eq!(clicks.decode(&press(MouseButton::Left, 2, 5)), Some(Mouse::Click(2, 5)));
+        assert_eq!(clicks.decode(&Event::Key(Key::MouseRelease(2, 5))), None);
+        assert_eq!(clicks.decode(&press(MouseButton::Left, 2, 5)), Some(Mouse::DoubleClick(2, 5)));
+        assert_eq!(clicks.decode(&press(MouseButton::Left, 2, 5)), Some(Mouse::Click(2, 5)));
+        assert_eq!(clicks.decode(&press(MouseButton::Left, 3, 5)), Some(Mouse::Click(3, 5)));
+    }
+
+    #[test]
+    fn wheel_presses_scroll() {
+        let mut clicks = Clicks::default();
+        assert_eq!(clicks.decode(&press(MouseButton::WheelUp, 0, 0)), Some(Mouse::WheelUp));
+        assert_eq!(clicks.decode(&press(MouseButton::WheelDown, 0, 0)), Some(Mouse::WheelDown));
+        assert_eq!(clicks.decode(&press(MouseButton::Right, 0, 0)), None);
+    }
+}
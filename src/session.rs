@@ -0,0 +1,200 @@
+use crate::index::{Index, ToBytes};
+use crate::model::Model;
+use quick_error::quick_error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const DEFAULT_SESSION: &str = "default";
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum SessionError {
+        NoDataDir {
+            display("Error could not determine a data directory for sessions")
+        }
+        BadName(name: String) {
+            display("Error bad session name \"{}\", use only letters, digits, - and _", name)
+        }
+        Io(err: io::Error) {
+            from()
+            display("Error reading or writing session: {}", err)
+        }
+        Format(err: serde_json::Error) {
+            from()
+            display("Error in session file: {}", err)
+        }
+    }
+}
+
+/// A pinned entry is stored the same way the index stores documents: the model type and a hex
+/// encoded bson document. This means pins survive without needing the index to look them up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedEntry {
+    pub mtype: String,
+    pub doc: String,
+}
+
+impl PinnedEntry {
+    pub fn from_model(m: &Model) -> Self {
+        Self {
+            mtype: m.mtype(),
+            doc: hex::encode(m.to_bytes()),
+        }
+    }
+
+    pub fn to_model(&self) -> Option<Model> {
+        let bytes = match hex::decode(&self.doc) {
+            Ok(b) => b,
+            Err(e) => {
+                error!("failed to decode pinned entry: {}", e);
+                return None;
+            }
+        };
+        match Model::from_bytes(&self.mtype, bytes) {
+            Ok(m) => Some(m),
+            Err(e) => {
+                error!("failed to load pinned entry: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Combatant {
+    pub name: String,
+    pub initiative: i32,
+    pub hit_points: i32,
+    pub max_hit_points: i32,
+    #[serde(default)]
+    pub conditions: Vec<String>,
+}
+
+/// Initiative order and whose turn it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Combat {
+    pub round: usize,
+    pub turn: usize,
+    pub combatants: Vec<Combatant>,
+}
+
+/// Everything about a game in progress that should outlive the process. Sessions are stored as
+/// JSON under the user's data directory, one file per named session:
+///
+/// ```text
+/// ~/.local/share/fuzzy5e/sessions/<name>.json
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub pinned: Vec<PinnedEntry>,
+    #[serde(default)]
+    pub combat: Combat,
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+impl Session {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            ..Self::default()
+        }
+    }
+
+    fn dir() -> Result<PathBuf, SessionError> {
+        match dirs::data_dir() {
+            Some(d) => Ok(d.join("fuzzy5e").join("sessions")),
+            None => Err(SessionError::NoDataDir),
+        }
+    }
+
+    /// The session's file. Names become file names, so they are kept to `[A-Za-z0-9_-]` to stay
+    /// inside the sessions directory.
+    fn path(name: &str) -> Result<PathBuf, SessionError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err(SessionError::BadName(String::from(name)));
+        }
+        Ok(Self::dir()?.join(format!("{}.json", name)))
+    }
+
+    /// Loads the named session. A session that has never been saved starts out empty.
+    pub fn load(name: &str) -> Result<Self, SessionError> {
+        let path = Self::path(name)?;
+        if !path.exists() {
+            debug!("no session at {:?}, starting fresh", path);
+            return Ok(Self::new(name));
+        }
+        let mut session: Session = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        session.name = String::from(name);
+        Ok(session)
+    }
+
+    pub fn save(&self) -> Result<(), SessionError> {
+        fs::create_dir_all(Self::dir()?)?;
+        let path = Self::path(&self.name)?;
+        debug!("saving session to {:?}", path);
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut session = Session::new("tuesday-group");
+        session.query = String::from("fireball");
+        session.pinned.push(PinnedEntry {
+            mtype: String::from("spell"),
+            doc: String::from("0500000000"),
+        });
+        session.combat.round = 2;
+        session.combat.combatants.push(Combatant {
+            name: String::from("Goblin"),
+            initiative: 14,
+            hit_points: 3,
+            max_hit_points: 7,
+            conditions: vec![String::from("prone")],
+        });
+        session.notes.push(String::from("the innkeeper is lying"));
+
+        let json = serde_json::to_string_pretty(&session).unwrap();
+        let loaded: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.name, "");
+        assert_eq!(loaded.query, "fireball");
+        assert_eq!(loaded.pinned[0].mtype, "spell");
+        assert_eq!(loaded.pinned[0].doc, "0500000000");
+        assert_eq!(loaded.combat.round, 2);
+        assert_eq!(loaded.combat.combatants[0].name, "Goblin");
+        assert_eq!(loaded.combat.combatants[0].conditions, vec!["prone"]);
+        assert_eq!(loaded.notes, vec!["the innkeeper is lying"]);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let loaded: Session = serde_json::from_str(r#"{"query": "orc"}"#).unwrap();
+        assert_eq!(loaded.query, "orc");
+        assert!(loaded.pinned.is_empty());
+        assert!(loaded.combat.combatants.is_empty());
+        assert!(loaded.notes.is_empty());
+    }
+
+    #[test]
+    fn names_stay_inside_the_sessions_directory() {
+        assert!(Session::path("../../etc/passwd").is_err());
+        assert!(Session::path("").is_err());
+        if let Ok(path) = Session::path("tuesday-group") {
+            assert!(path.ends_with("sessions/tuesday-group.json"));
+        }
+    }
+}
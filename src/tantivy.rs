@@ -1,10 +1,11 @@
 use crate::index;
 use hex::ToHex;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 use tantivy::directory::MmapDirectory;
 
-use tantivy::collector::TopDocs;
+use tantivy::collector::{Count, TopDocs};
 use tantivy::query::QueryParser;
 use tantivy::schema::*;
 use tantivy::tokenizer::*;
@@ -103,21 +104,31 @@ impl Tantivy {
         qs: &str,
         limit: usize,
     ) -> Result<Vec<Document>, index::IndexError> {
+        self.do_query_page(qs, 0, limit).map(|(docs, _total)| docs)
+    }
+
+    /// Returns up to `limit` documents after skipping `offset`, and the total number of matches.
+    fn do_query_page(
+        &self,
+        qs: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Document>, usize), index::IndexError> {
         let searcher = self.reader.searcher();
         let mut results = Vec::new();
 
-        trace!("do_query: {}", qs);
+        trace!("do_query: {} from {}", qs, offset);
 
         match self.qp.parse_query(qs) {
-            Ok(query) => match searcher.search(&query, &TopDocs::with_limit(limit)) {
-                Ok(res) => {
-                    for (_score, doc_address) in res {
+            Ok(query) => match searcher.search(&query, &(TopDocs::with_limit(offset + limit), Count)) {
+                Ok((res, total)) => {
+                    for (_score, doc_address) in res.into_iter().skip(offset) {
                         if let Ok(doc) = searcher.doc(doc_address) {
                             results.push(doc);
                         }
                     }
-                    debug!("do_query got {} results", results.len());
-                    Ok(results)
+                    debug!("do_query got {} of {} results", results.len(), total);
+                    Ok((results, total))
                 }
                 Err(err) => {
                     error!("search error: {}", err);
@@ -130,6 +141,28 @@ impl Tantivy {
             }
         }
     }
+
+    /// Pulls the mtype and stored document bytes out of search results
+    fn doc_bytes(&self, docs: Vec<Document>) -> Vec<(String, Vec<u8>)> {
+        let mut r = Vec::new();
+        let raw = self.index.schema().get_field("doc").unwrap();
+        let mtype = self.index.schema().get_field("mtype").unwrap();
+        for doc in docs {
+            if let Some(val) = doc.get_first(raw) {
+                if let Some(t) = doc.get_first(mtype) {
+                    r.push((
+                        t.text().unwrap().into(),
+                        hex::decode(val.text().unwrap()).unwrap(),
+                    ));
+                } else {
+                    error!("failed to get mtype field");
+                }
+            } else {
+                error!("failed to get raw field");
+            }
+        }
+        r
+    }
 }
 
 impl Default for TantivyOptions {
@@ -191,24 +224,23 @@ impl index::Indexer for Tantivy {
     /// Queries tantivy for document byte arrays
     fn query(&self, col: &str, qs: &str) -> Result<Vec<(String, Vec<u8>)>, index::IndexError> {
         let docs = self.do_query(col, qs, RESULT_LIMIT)?;
-        let mut r = Vec::new();
-        let raw = self.index.schema().get_field("doc").unwrap();
-        let mtype = self.index.schema().get_field("mtype").unwrap();
-        for doc in docs {
-            if let Some(val) = doc.get_first(raw) {
-                if let Some(t) = doc.get_first(mtype) {
-                    r.push((
-                        t.text().unwrap().into(),
-                        hex::decode(val.text().unwrap()).unwrap(),
-                    ));
-                } else {
-                    error!("failed to get mtype field");
-                }
-            } else {
-                error!("failed to get raw field");
-            }
-        }
-        Ok(r)
+        Ok(self.doc_bytes(docs))
+    }
+
+    /// Queries a page of document byte arrays. Tantivy knows the total number of matches.
+    fn query_page(
+        &self,
+        _col: &str,
+        qs: &str,
+        cursor: &mut index::Cursor,
+        limit: usize,
+    ) -> Result<index::Page, index::IndexError> {
+        let (docs, total) = self.do_query_page(qs, cursor.offset, limit)?;
+        cursor.offset += docs.len();
+        Ok(index::Page {
+            docs: self.doc_bytes(docs),
+            total: Some(total),
+        })
     }
 
     /// Queries tantivy for matching ids
@@ -227,4 +259,33 @@ impl index::Indexer for Tantivy {
     fn flush_all(&self, col: &str) -> Result<(), index::IndexError> {
         Ok(())
     }
+
+    /// Walks the name terms starting with `word`, ranking them by how many documents have them.
+    fn suggest(&self, _col: &str, word: &str, limit: usize) -> Result<Vec<String>, index::IndexError> {
+        let prefix = word.to_lowercase();
+        let name = self.index.schema().get_field("name").unwrap();
+        let searcher = self.reader.searcher();
+        let mut found: HashMap<String, u32> = HashMap::new();
+        for segment in searcher.segment_readers() {
+            let inverted_index = segment.inverted_index(name);
+            let mut terms = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream();
+            while terms.advance() {
+                if !terms.key().starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                if let Ok(term) = std::str::from_utf8(terms.key()) {
+                    if term.len() > prefix.len() {
+                        *found.entry(String::from(term)).or_default() += terms.value().doc_freq;
+                    }
+                }
+            }
+        }
+        let mut found: Vec<(String, u32)> = found.into_iter().collect();
+        found.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(found.into_iter().take(limit).map(|(term, _)| term).collect())
+    }
 }
@@ -0,0 +1,411 @@
+use crate::icons::{IconError, IconSet};
+use lazy_static::lazy_static;
+use quick_error::quick_error;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use tuikit::prelude::{Attr, Color, Effect};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ThemeError {
+        Io(err: io::Error) {
+            from()
+            display("Error reading theme: {}", err)
+        }
+        Format(err: toml::de::Error) {
+            from()
+            display("Error in theme file: {}", err)
+        }
+        Icons(err: IconError) {
+            from()
+            display("{}", err)
+        }
+        UnknownTheme(name: String) {
+            display("Error unknown theme \"{}\"", name)
+        }
+        UnknownStyle(style: String) {
+            display("Error unknown style \"{}\" in theme", style)
+        }
+        UnknownColor(color: String) {
+            display("Error unknown color \"{}\" in theme", color)
+        }
+        UnknownColorMode(mode: String) {
+            display("Error unknown color mode \"{}\", expected auto, none, 256 or truecolor", mode)
+        }
+    }
+}
+
+lazy_static! {
+    static ref THEME: RwLock<Theme> = RwLock::new(Theme::default());
+}
+
+/// Replaces the theme used for drawing.
+pub fn set(theme: Theme) {
+    if let Ok(mut t) = THEME.write() {
+        *t = theme;
+    }
+}
+
+/// The attribute for a style in the current theme.
+pub fn attr(style: Style) -> Attr {
+    THEME
+        .read()
+        .ok()
+        .and_then(|t| t.styles.get(&style).cloned())
+        .unwrap_or_default()
+}
+
+/// Semantic styles. Drawing code asks for one of these rather than naming colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+    /// The name at the top of an entry
+    Title,
+    SpellTitle,
+    /// Field names such as "Casting Time:"
+    Label,
+    /// Horizontal rules in stat blocks
+    Rule,
+    /// Section headings such as "Actions"
+    Heading,
+    Link,
+    SelectedLink,
+    SelectedRow,
+    PaneTitle,
+    Prompt,
+    /// Suggested completion shown after the cursor
+    Completion,
+    ActiveTab,
+    /// Text matching an in-pane find
+    FindMatch,
+    /// The find match jumped to last
+    FindCurrent,
+    Spell,
+    Monster,
+    Class,
+    Subclass,
+    Condition,
+    MagicSchool,
+    Equipment,
+    Feature,
+}
+
+impl FromStr for Style {
+    type Err = ThemeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "title" => Self::Title,
+            "spell-title" => Self::SpellTitle,
+            "label" => Self::Label,
+            "rule" => Self::Rule,
+            "heading" => Self::Heading,
+            "link" => Self::Link,
+            "selected-link" => Self::SelectedLink,
+            "selected-row" => Self::SelectedRow,
+            "pane-title" => Self::PaneTitle,
+            "prompt" => Self::Prompt,
+            "completion" => Self::Completion,
+            "active-tab" => Self::ActiveTab,
+            "find-match" => Self::FindMatch,
+            "find-current" => Self::FindCurrent,
+            "spell" => Self::Spell,
+            "monster" => Self::Monster,
+            "class" => Self::Class,
+            "subclass" => Self::Subclass,
+            "condition" => Self::Condition,
+            "magic-school" => Self::MagicSchool,
+            "equipment" => Self::Equipment,
+            "feature" => Self::Feature,
+            _ => return Err(ThemeError::UnknownStyle(String::from(s))),
+        })
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    /// Effects only, e.g. when `NO_COLOR` is set
+    None,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorMode {
+    /// Picks a mode from the environment: `NO_COLOR` turns colors off and `COLORTERM` advertises
+    /// truecolor support.
+    pub fn detect() -> Self {
+        if std::env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty()) {
+            return Self::None;
+        }
+        match std::env::var("COLORTERM") {
+            Ok(ref v) if v == "truecolor" || v == "24bit" => Self::TrueColor,
+            _ => Self::Ansi256,
+        }
+    }
+
+    fn apply(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::None, _) => Color::Default,
+            (Self::Ansi256, Color::Rgb(r, g, b)) => Color::AnsiValue(rgb_to_ansi(r, g, b)),
+            (_, c) => c,
+        }
+    }
+}
+
+/// Accepts `auto`, `none`, `256` and `truecolor`.
+impl FromStr for ColorMode {
+    type Err = ThemeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::detect()),
+            "none" => Ok(Self::None),
+            "256" => Ok(Self::Ansi256),
+            "truecolor" | "24bit" => Ok(Self::TrueColor),
+            _ => Err(ThemeError::UnknownColorMode(String::from(s))),
+        }
+    }
+}
+
+/// Nearest entry in the 6x6x6 color cube or the grayscale ramp of the 256 color palette.
+fn rgb_to_ansi(r: u8, g: u8, b: u8) -> u8 {
+    let cube = |v: u8| -> u8 {
+        if v < 48 {
+            0
+        } else if v < 115 {
+            1
+        } else {
+            (v - 35) / 40
+        }
+    };
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            248..=255 => 231,
+            _ => 232 + (r - 8) / 10,
+        };
+    }
+    16 + 36 * cube(r) + 6 * cube(g) + cube(b)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    bold: bool,
+    #[serde(default)]
+    dim: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    reverse: bool,
+    #[serde(default)]
+    blink: bool,
+}
+
+impl StyleSpec {
+    fn to_attr(&self, mode: ColorMode) -> Result<Attr, ThemeError> {
+        let color = |c: &Option<String>| -> Result<Color, ThemeError> {
+            match c {
+                Some(c) => Ok(mode.apply(parse_color(c)?)),
+                None => Ok(Color::Default),
+            }
+        };
+        let mut effect = Effect::empty();
+        for (on, e) in &[
+            (self.bold, Effect::BOLD),
+            (self.dim, Effect::DIM),
+            (self.underline, Effect::UNDERLINE),
+            (self.reverse, Effect::REVERSE),
+            (self.blink, Effect::BLINK),
+        ] {
+            if *on {
+                effect |= *e;
+            }
+        }
+        Ok(Attr {
+            fg: color(&self.fg)?,
+            bg: color(&self.bg)?,
+            effect,
+        })
+    }
+}
+
+/// Parses a color name, a palette index or `#rrggbb`.
+fn parse_color(s: &str) -> Result<Color, ThemeError> {
+    let unknown = || ThemeError::UnknownColor(String::from(s));
+    if let Ok(n) = s.parse::<u8>() {
+        return Ok(Color::AnsiValue(n));
+    }
+    if s.starts_with('#') && s.len() == 7 {
+        let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| unknown());
+        return Ok(Color::Rgb(channel(1)?, channel(3)?, channel(5)?));
+    }
+    Ok(match s {
+        "default" => Color::Default,
+        "black" => Color::BLACK,
+        "red" => Color::RED,
+        "green" => Color::GREEN,
+        "yellow" => Color::YELLOW,
+        "blue" => Color::BLUE,
+        "magenta" => Color::MAGENTA,
+        "cyan" => Color::CYAN,
+        "white" => Color::WHITE,
+        "light-black" => Color::LIGHT_BLACK,
+        "light-red" => Color::LIGHT_RED,
+        "light-green" => Color::LIGHT_GREEN,
+        "light-yellow" => Color::LIGHT_YELLOW,
+        "light-blue" => Color::LIGHT_BLUE,
+        "light-magenta" => Color::LIGHT_MAGENTA,
+        "light-cyan" => Color::LIGHT_CYAN,
+        "light-white" => Color::LIGHT_WHITE,
+        _ => return Err(unknown()),
+    })
+}
+
+/// A theme file, either one of the bundled themes or a user theme in
+/// `~/.config/fuzzy5e/themes/<name>.toml`:
+///
+/// ```toml
+/// # Start from one of the bundled themes, dark or light. Defaults to dark.
+/// base = "light"
+/// # Icons shown next to results: emoji, nerd, ascii or none
+/// icons = "ascii"
+///
+/// [styles]
+/// spell-title = { fg = "#5f00af", bold = true }
+/// selected-row = { reverse = true }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct ThemeFile {
+    base: Option<String>,
+    icons: Option<String>,
+    #[serde(default)]
+    styles: BTreeMap<String, StyleSpec>,
+}
+
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "dark" => Some(include_str!("../themes/dark.toml")),
+        "light" => Some(include_str!("../themes/light.toml")),
+        _ => None,
+    }
+}
+
+pub struct Theme {
+    styles: HashMap<Style, Attr>,
+    icons: Option<IconSet>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::load("dark", ColorMode::Ansi256).expect("bundled theme is valid")
+    }
+}
+
+impl Theme {
+    /// Finds a user theme by name, or accepts a path to a theme file.
+    fn path(name: &str) -> Result<PathBuf, ThemeError> {
+        if Path::new(name).is_file() {
+            return Ok(PathBuf::from(name));
+        }
+        dirs::config_dir()
+            .map(|d| d.join("fuzzy5e").join("themes").join(format!("{}.toml", name)))
+            .filter(|p| p.exists())
+            .ok_or_else(|| ThemeError::UnknownTheme(String::from(name)))
+    }
+
+    /// Loads a bundled or user theme. User themes are layered on top of their base theme so
+    /// they only need to list the styles they change.
+    pub fn load(name: &str, mode: ColorMode) -> Result<Self, ThemeError> {
+        let mut theme = Self {
+            styles: HashMap::new(),
+            icons: None,
+        };
+        match builtin(name) {
+            Some(contents) => theme.merge(toml::from_str(contents)?, mode)?,
+            None => {
+                debug!("loading theme {}", name);
+                let file: ThemeFile = toml::from_str(&fs::read_to_string(Self::path(name)?)?)?;
+                let base = file.base.clone().unwrap_or_else(|| String::from("dark"));
+                let contents =
+                    builtin(&base).ok_or_else(|| ThemeError::UnknownTheme(base.clone()))?;
+                theme.merge(toml::from_str(contents)?, mode)?;
+                theme.merge(file, mode)?;
+            }
+        }
+        Ok(theme)
+    }
+
+    /// The icon set chosen by the theme, if any
+    pub fn icons(&self) -> Option<IconSet> {
+        self.icons
+    }
+
+    fn merge(&mut self, file: ThemeFile, mode: ColorMode) -> Result<(), ThemeError> {
+        if let Some(icons) = &file.icons {
+            self.icons = Some(icons.parse()?);
+        }
+        for (name, spec) in &file.styles {
+            self.styles.insert(name.parse()?, spec.to_attr(mode)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_styles_and_color_modes() {
+        assert_eq!("selected-row".parse::<Style>().unwrap(), Style::SelectedRow);
+        assert_eq!("find-current".parse::<Style>().unwrap(), Style::FindCurrent);
+        assert!(matches!("selected_row".parse::<Style>(), Err(ThemeError::UnknownStyle(_))));
+        assert_eq!("none".parse::<ColorMode>().unwrap(), ColorMode::None);
+        assert_eq!("256".parse::<ColorMode>().unwrap(), ColorMode::Ansi256);
+        assert_eq!("24bit".parse::<ColorMode>().unwrap(), ColorMode::TrueColor);
+        assert!(matches!("16".parse::<ColorMode>(), Err(ThemeError::UnknownColorMode(_))));
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("light-cyan").unwrap(), Color::LIGHT_CYAN);
+        assert_eq!(parse_color("208").unwrap(), Color::AnsiValue(208));
+        assert_eq!(parse_color("#5f00af").unwrap(), Color::Rgb(0x5f, 0, 0xaf));
+        for bad in &["#5f00a", "#5g00af", "256", "purple"] {
+            assert!(matches!(parse_color(bad), Err(ThemeError::UnknownColor(_))));
+        }
+    }
+
+    #[test]
+    fn color_modes_adjust_theme_colors() {
+        let spec: StyleSpec = toml::from_str("fg = \"#5f00af\"\nbg = \"red\"\nbold = true").unwrap();
+        let attr = spec.to_attr(ColorMode::Ansi256).unwrap();
+        assert_eq!(attr.fg, Color::AnsiValue(55));
+        assert_eq!(attr.bg, Color::RED);
+        assert_eq!(attr.effect, Effect::BOLD);
+        let attr = spec.to_attr(ColorMode::None).unwrap();
+        assert_eq!((attr.fg, attr.bg), (Color::Default, Color::Default));
+        assert_eq!(attr.effect, Effect::BOLD);
+    }
+
+    #[test]
+    fn bundled_themes_load() {
+        for name in &["dark", "light"] {
+            let theme = Theme::load(name, ColorMode::TrueColor).unwrap();
+            let selected = theme.styles[&Style::SelectedRow];
+            assert!(selected.effect.contains(Effect::REVERSE));
+        }
+        assert!(matches!(
+            Theme::load("no-such-theme", ColorMode::None),
+            Err(ThemeError::UnknownTheme(_))
+        ));
+    }
+}